@@ -0,0 +1,65 @@
+#!/usr/bin/env bash
+# Measures how long bash takes to source a bundle of many programs compared to sourcing the
+# script of every program, and what the first TAB on one of the programs costs with the bundle.
+# Usage: bench/bundle.sh [programs] [options] [iterations]
+# The results are written to bench_bundle_output.txt in the repository root.
+
+set -e
+
+programs=${1:-50}
+options=${2:-200}
+iterations=${3:-10}
+root=$(cd "$(dirname "$0")/.." && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "${work}"' EXIT
+
+cd "${root}"
+cargo build --release --quiet
+
+for (( p = 0; p < programs; p++ )); do
+    {
+        echo "program_name: tool${p}"
+        for (( i = 0; i < options; i++ )); do
+            echo "option:"
+            echo "    long: --option-${i}"
+            echo "    description: Description of option ${i}"
+            if (( i % 2 == 0 )); then
+                echo "    fixed_values: [alpha, beta, gamma-${i}]"
+            fi
+        done
+    } > "${work}/tool${p}.cfg"
+
+    ./target/release/autocshell -c "${work}/tool${p}.cfg" -s bash -o "${work}/tool${p}"
+done
+
+./target/release/autocshell bundle -s bash -o "${work}/bundle" "${work}"/tool*.cfg
+
+# Prints the average time the code takes in a new shell in milliseconds.
+measure() {
+    local label=$1 code=$2 total=0 elapsed i
+    for (( i = 0; i < iterations; i++ )); do
+        elapsed=$(bash -c '
+            compopt() { :; }
+            start=${EPOCHREALTIME/[.,]/}
+            '"${code}"'
+            end=${EPOCHREALTIME/[.,]/}
+            echo $(( end - start ))')
+        (( total += elapsed ))
+    done
+    printf '%-40s %8.2f ms\n' "${label}" "$(( total / iterations ))e-3"
+}
+
+# Runs the completion registered for tool0 on `tool0 --`, as bash would on a TAB.
+first_tab='
+    COMP_LINE="tool0 --" COMP_POINT=8 COMP_WORDS=( tool0 -- ) COMP_CWORD=1
+    while [[ $(complete -p tool0) =~ -F\ ([^ ]+) ]]; do
+        "${BASH_REMATCH[1]}" tool0 -- tool0
+        (( $? == 124 )) || break
+    done'
+
+{
+    echo "bash ${BASH_VERSION}, ${programs} programs, ${options} options each, ${iterations} iterations"
+    measure "source every script" "for script in ${work}/tool*.bash; do source \"\${script}\"; done"
+    measure "source the bundle" "source ${work}/bundle/autocshell.bash"
+    measure "source the bundle and TAB on tool0" "source ${work}/bundle/autocshell.bash; ${first_tab}"
+} | tee bench_bundle_output.txt
@@ -0,0 +1,74 @@
+#!/usr/bin/env bash
+# Measures how long generated bash scripts take to load and to answer a TAB for a synthetic
+# spec with many options. zsh scripts are neither tuned nor measured.
+# Usage: bench/large_spec.sh [options] [iterations]
+# The results are written to bench_output.txt in the repository root.
+
+set -e
+
+options=${1:-2000}
+iterations=${2:-20}
+root=$(cd "$(dirname "$0")/.." && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "${work}"' EXIT
+
+cd "${root}"
+cargo build --release --quiet
+
+{
+    echo "program_name: bigtool"
+    for (( i = 0; i < options; i++ )); do
+        if (( i % 250 == 0 )); then
+            echo "section: Group $(( i / 250 ))"
+        fi
+        echo "option:"
+        echo "    long: --option-${i}"
+        echo "    description: Description of option ${i}"
+        case $(( i % 4 )) in
+        0) echo "    fixed_values: [alpha, beta, gamma-${i}]" ;;
+        1) echo "    files: *.txt" ;;
+        2) echo "    value_type: user" ;;
+        esac
+    done
+} > "${work}/bigtool.cfg"
+
+./target/release/autocshell -c "${work}/bigtool.cfg" -s bash -o "${work}/bigtool"
+
+# Runs the completion function for a command line that ends at the cursor.
+complete_line() {
+    COMP_LINE=$1
+    COMP_POINT=${#COMP_LINE}
+    read -r -a COMP_WORDS <<< "${COMP_LINE}"
+    [[ ${COMP_LINE} == *" " ]] && COMP_WORDS+=( "" )
+    COMP_CWORD=$(( ${#COMP_WORDS[@]} - 1 ))
+    _bigtool_completions bigtool
+}
+
+# Prints the average time of a TAB on the line in milliseconds.
+measure() {
+    local start end i
+    start=${EPOCHREALTIME/[.,]/}
+    for (( i = 0; i < iterations; i++ )); do
+        complete_line "$1"
+    done
+    end=${EPOCHREALTIME/[.,]/}
+    printf '%-40s %8.2f ms (%d candidates)\n' "'$1'" \
+        "$(( (end - start) / iterations ))e-3" "${#COMPREPLY[@]}"
+}
+
+compopt() { :; }
+
+{
+    echo "bash ${BASH_VERSION}, ${options} options, ${iterations} iterations"
+    start=${EPOCHREALTIME/[.,]/}
+    source "${work}/bigtool.bash"
+    end=${EPOCHREALTIME/[.,]/}
+    printf '%-40s %8.2f ms\n' "load" "$(( end - start ))e-3"
+
+    measure "bigtool "
+    measure "bigtool --"
+    measure "bigtool --option-19"
+    measure "bigtool --option-$(( options - 4 )) "
+    measure "bigtool --option-$(( options - 4 )) a"
+    measure "bigtool --option-3 --option-$(( options - 1 ))"
+} | tee bench_output.txt
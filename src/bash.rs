@@ -7,44 +7,1047 @@ pub fn get_fixed_values_var_name_for_option(program_option: &ProgramOption) -> S
         &program_option.short
     };
 
+    let name = option.trim_start_matches(['-', '+']);
+    let prefix = if option.starts_with('+') { "plus_" } else { "" };
+
+    format!(
+        "{prefix}{name}_fixed_values",
+        prefix = prefix,
+        name = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    )
+}
+
+pub fn get_fixed_values_var_name_for_arg(program_option: &ProgramOption, position: usize) -> String {
+    let var_name = get_fixed_values_var_name_for_option(program_option);
+    format!(
+        "{prefix}_{position}_fixed_values",
+        prefix = var_name.trim_end_matches("_fixed_values"),
+        position = position + 1
+    )
+}
+
+fn compgen_files(filter: &FileFilter, word: &str) -> String {
+    let hidden_filter = if filter.include_hidden {
+        ""
+    } else {
+        r#" | grep -Ev '(^|/)\.[^/]*$'"#
+    };
+
+    match &filter.kind {
+        FileKind::All => format!(r#"$(compgen -f -- "{}"{})"#, word, hidden_filter),
+        FileKind::Directories => format!(r#"$(compgen -d -- "{}"{})"#, word, hidden_filter),
+        FileKind::Glob(glob) => format!(
+            r#"$(compgen -d -- "{word}"{hidden}) $(compgen -f -X '!{glob}' -- "{word}"{hidden})"#,
+            word = word,
+            glob = glob,
+            hidden = hidden_filter
+        ),
+        FileKind::Executables => format!(
+            r#"$(compgen -f -- "{}"{} | while read -r file; do [[ -d ${{file}} || -x ${{file}} ]] && echo "${{file}}"; done)"#,
+            word, hidden_filter
+        ),
+    }
+}
+
+fn compgen_value_kind(kind: ValueKind, word: &str) -> String {
+    let compgen = |args: &str| format!(r#"$(compgen {} -- "{}")"#, args, word);
+    let compgen_output_of = |command: &str| {
+        format!(
+            r#"$(compgen -W "$({} 2>/dev/null)" -- "{}")"#,
+            command, word
+        )
+    };
+
+    match kind {
+        ValueKind::Command => compgen("-c"),
+        ValueKind::User => compgen("-u"),
+        ValueKind::Group => compgen("-g"),
+        ValueKind::Host => compgen("-A hostname"),
+        ValueKind::Port => compgen("-A service"),
+        ValueKind::Pid => compgen_output_of("ps -axo pid="),
+        ValueKind::Signal => compgen("-A signal"),
+        ValueKind::EnvVar => compgen("-e"),
+        ValueKind::Interface => compgen_output_of("ls /sys/class/net"),
+        ValueKind::Locale => compgen_output_of("locale -a"),
+        ValueKind::CommandLine => compgen("-c"),
+        ValueKind::Timezone => format!(
+            r#"$(cd /usr/share/zoneinfo 2>/dev/null && compgen -f -- "{}")"#,
+            word
+        ),
+    }
+}
+
+fn value_type_function_name(cfg: &Config, name: &str) -> String {
+    format!("{}_{}_values", cfg.function_name(), name)
+}
+
+/// Returns the command substitutions that produce the candidates of `spec` for `word`.
+fn compgen_words(cfg: &Config, spec: &ValueSpec, fixed_values: &str, word: &str) -> String {
+    match spec {
+        ValueSpec::Any => String::new(),
+        ValueSpec::Files(filter) => compgen_files(filter, word),
+        ValueSpec::Fixed(_) => format!(r#"$(compgen -W "{}" -- "{}")"#, fixed_values, word),
+        ValueSpec::Kind(kind) => compgen_value_kind(*kind, word),
+        ValueSpec::Command(command) => format!(
+            r#"$(compgen -W "$({})" -- "{}")"#,
+            values_command_output(cfg, command),
+            word
+        ),
+        ValueSpec::Named(name) => format!(
+            r#"$({} "{}")"#,
+            value_type_function_name(cfg, name),
+            word
+        ),
+    }
+}
+
+/// The value given on the line to the option with the given name, for use in values commands.
+fn option_value(cfg: &Config, name: &str) -> String {
+    let names = cfg
+        .option_named(name)
+        .map(|option| option.names())
+        .unwrap_or_else(|| vec![name]);
+
     format!(
-        "{prefix}_fixed_values",
-        prefix = option.trim_start_matches('-')
+        r#""$({function_name}_option_value {names})""#,
+        function_name = cfg.function_name(),
+        names = names
+            .iter()
+            .map(|name| case_pattern(name))
+            .collect::<Vec<_>>()
+            .join(" ")
     )
 }
 
-fn format_option_cases(program_option: &ProgramOption) -> String {
-    let compreply = format!(
+/// Runs the command, through the disk cache if it has a cache_ttl. The command is keyed
+/// by its text and the values of the options it refers to.
+fn values_command_output(cfg: &Config, command: &ValuesCommand) -> String {
+    let expanded = expand_option_references(&command.command, |name| option_value(cfg, name));
+    match command.cache_ttl {
+        None => format!("{} 2>/dev/null", expanded),
+        Some(ttl) => format!(
+            "{function_name}_cached {ttl} '{command}'{references}",
+            function_name = cfg.function_name(),
+            ttl = ttl,
+            command = expanded.replace('\'', r#"'\''"#),
+            references = option_references(&command.command)
+                .iter()
+                .map(|(_, name)| format!(" {}", option_value(cfg, name)))
+                .collect::<String>()
+        ),
+    }
+}
+
+fn format_cache_function(cfg: &Config) -> String {
+    if !cfg.uses_cache() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+{function_name}_cached() {{
+    local ttl=$1 command=$2
+    local dir=${{XDG_CACHE_HOME:-${{HOME}}/.cache}}/autocshell/{prog_name}
+    local key modified output
+    key=$(printf '%s\n' "${{@:2}}" | cksum)
+    local file=${{dir}}/${{key%% *}}
+    # GNU and busybox stat take -c, BSD and macOS stat take -f.
+    modified=$(stat -c %Y "${{file}}" 2>/dev/null || stat -f %m "${{file}}" 2>/dev/null) ||
+        modified=0
+    if (( $(date +%s) - modified < ttl )); then
+        cat "${{file}}"
+        return
+    fi
+
+    output=$(eval "${{command}}" 2>/dev/null)
+    if [[ -n ${{output}} ]] && mkdir -p "${{dir}}"; then
+        printf '%s\n' "${{output}}" > "${{file}}.$$" && mv -f "${{file}}.$$" "${{file}}"
+    fi
+    printf '%s\n' "${{output}}"
+}}
+"#,
+        function_name = cfg.function_name(),
+        prog_name = cfg.program_name
+    )
+}
+
+/// Prints the value of the last occurrence of any of the given option names before the
+/// current word, whether it was given as `--opt value` or `--opt=value`.
+fn format_option_value_function(cfg: &Config) -> String {
+    if !cfg.uses_option_references() {
+        return String::new();
+    }
+
+    format!(
         r#"
-            COMPREPLY=( $(compgen {} "${{current}}") )
+{function_name}_option_value() {{
+    local i name value=""
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        for name in "$@"; do
+            if [[ ${{COMP_WORDS[i]}} == "${{name}}" ]]; then
+                if [[ ${{COMP_WORDS[i+1]}} == = ]]; then
+                    (( i + 2 < COMP_CWORD )) && value=${{COMP_WORDS[i+2]}}
+                elif (( i + 1 < COMP_CWORD )); then
+                    value=${{COMP_WORDS[i+1]}}
+                fi
+            elif [[ ${{COMP_WORDS[i]}} == "${{name}}="* ]]; then
+                value=${{COMP_WORDS[i]#*=}}
+            fi
+        done
+    done
+    printf '%s' "${{value}}"
+}}
+"#,
+        function_name = cfg.function_name()
+    )
+}
+
+/// Emits one function per named value type, printing the type's candidates for the word in $1.
+fn format_value_type_functions(cfg: &Config) -> String {
+    cfg.value_types
+        .iter()
+        .map(|value_type| {
+            let fixed_values = match &value_type.spec {
+                ValueSpec::Fixed(values) => values.join(" "),
+                _ => String::new(),
+            };
+
+            format!(
+                r#"
+{function}() {{
+    printf '%s\n' {words}
+}}
+"#,
+                function = value_type_function_name(cfg, &value_type.name),
+                words = compgen_words(cfg, &value_type.spec, &fixed_values, "$1")
+            )
+        })
+        .collect()
+}
+
+fn format_prefix_compreply(prefix: &str) -> String {
+    format!(r#"COMPREPLY=( "${{COMPREPLY[@]/#/{}}}" )"#, prefix)
+}
+
+fn format_compreply(compgen_words: &str) -> String {
+    format!(
+        r#"
+            COMPREPLY=( {} )
             return 0
             ;;
         "#,
-        if program_option.accepts_files {
-            "-f".to_owned()
-        } else if !program_option.fixed_values.is_empty() {
-            format!(
-                r#"-W "${{{}}}" --"#,
+        compgen_words
+    )
+}
+
+/// The code that completes the value of the option, with its fixed values written in place
+/// so that it doesn't depend on the variables of the completion function.
+fn option_value_code(cfg: &Config, program_option: &ProgramOption) -> String {
+    format!(
+        "COMPREPLY=( {} )",
+        compgen_words(
+            cfg,
+            &program_option.value_spec(),
+            &program_option.fixed_values.join(" "),
+            "${current}",
+        )
+    )
+}
+
+/// With complete_env_names, offers the environment variables when the initial word of a
+/// command starts like one (e.g OURTOOL_<TAB>) and otherwise hands over to the previous initial
+/// word completion. Their values can't be completed: bash completes file names after `VAR=` in
+/// front of a command without calling any completion function, including this one.
+fn format_env_names_function(cfg: &Config) -> String {
+    let env_vars = cfg.all_env_vars();
+    if !cfg.complete_env_names || env_vars.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+
+{function_name}_env_names() {{
+    local current=${{COMP_WORDS[COMP_CWORD]}}
+    if [[ -n ${{current}} && ${{current}} != *[!A-Za-z0-9_]* ]]; then
+        COMPREPLY=( $(compgen -W "{names}" -S = -- "${{current}}") )
+        if (( ${{#COMPREPLY[@]}} > 0 )); then
+            compopt -o nospace
+            return 0
+        fi
+    fi
+
+    if [[ -n ${{{function_name}_env_names_next}} ]]; then
+        "${{{function_name}_env_names_next}}" "$@"
+    fi
+}}
+
+if [[ $(complete -p -I 2>/dev/null) =~ -F\ ([^ ]+) && ${{BASH_REMATCH[1]}} != {function_name}_env_names ]]; then
+    {function_name}_env_names_next=${{BASH_REMATCH[1]}}
+fi
+complete -I -F {function_name}_env_names -o bashdefault -o default 2>/dev/null
+"#,
+        function_name = cfg.function_name(),
+        names = env_vars
+            .iter()
+            .map(|env_var| env_var.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Free-form values have nothing to complete, so instead of falling back to file names the
+/// value's description is shown as two entries that can't be inserted into the line. They are
+/// only offered when bash lists the candidates on a second TAB, since menu completion and
+/// show-all-if-ambiguous insert candidates too.
+fn value_hint_code(o: &ProgramOption) -> String {
+    let value_name = if o.value_name.is_empty() { "value" } else { &o.value_name };
+    let mut description = o.hint.clone();
+    if !o.default_value.is_empty() {
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description.push_str(&format!("(default: {})", o.default_value));
+    }
+    if description.is_empty() {
+        description = "any value".to_owned();
+    }
+
+    format!(
+        r#"compopt +o default +o bashdefault 2>/dev/null; [[ -z ${{current}} && ${{COMP_TYPE}} == 63 ]] && COMPREPLY=( '<{value_name}>' '{description}' )"#,
+        value_name = value_name.replace('\'', "'\\''"),
+        description = description.replace('\'', "'\\''")
+    )
+}
+
+/// Completes the value after an option by looking its name up in a table of the code that
+/// completes its values. The tables are only built on the first completion that needs them.
+/// bash older than 4.2 can't declare a global associative array from a function, so there the
+/// name is found in a string of `name:index` words instead.
+fn format_complete_value_function(cfg: &Config) -> String {
+    let entries = cfg
+        .program_options
+        .iter()
+        .filter(|o| {
+            o.arity.is_none()
+                && !o.takes_value_list()
+                && !o.delegates_command_line()
+                && o.attached_value != AttachedValue::Required
+        })
+        .filter_map(|o| {
+            if o.value_spec() != ValueSpec::Any {
+                Some((o, option_value_code(cfg, o)))
+            } else if o.describes_value() {
+                Some((o, value_hint_code(o)))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let names = entries
+        .iter()
+        .enumerate()
+        .flat_map(|(index, (o, _))| {
+            o.names()
+                .into_iter()
+                .map(move |name| (name.replace('\'', "'\\''"), index))
+        })
+        .collect::<Vec<_>>();
+
+    let lookup = if entries.is_empty() {
+        "\n    return 1".to_owned()
+    } else {
+        format!(
+            r#"
+    [[ -n ${{previous}} ]] || return 1
+    local index
+    if (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 2) )); then
+        (( ${{#{function_name}_values[@]}} )) || declare -gA {function_name}_values=({table}
+        )
+        index=${{{function_name}_values[${{previous}}]}}
+    elif [[ ' {names} ' =~ " ${{previous}}:"([0-9]+)" " ]]; then
+        index=${{BASH_REMATCH[1]}}
+    fi
+    [[ -n ${{index}} ]] || return 1
+    (( ${{#{function_name}_value_code[@]}} )) || {function_name}_value_code=({codes}
+    )
+    eval "${{{function_name}_value_code[index]}}"
+    return 0"#,
+            function_name = cfg.function_name(),
+            table = names
+                .iter()
+                .map(|(name, index)| format!("\n            ['{}']={}", name, index))
+                .collect::<String>(),
+            names = names
+                .iter()
+                .map(|(name, index)| format!("{}:{}", name, index))
+                .collect::<Vec<_>>()
+                .join(" "),
+            codes = entries
+                .iter()
+                .map(|(_, code)| format!("\n        '{}'", code.replace('\'', "'\\''")))
+                .collect::<String>()
+        )
+    };
+
+    format!(
+        r#"
+{function_name}_complete_value() {{{value_list_lookup}{position_lookup}{lookup}
+}}
+"#,
+        function_name = cfg.function_name(),
+        value_list_lookup = format_value_list_lookup(cfg),
+        position_lookup = format_position_lookup(cfg),
+        lookup = lookup
+    )
+}
+
+/// Quotes option names that contain characters with a special meaning in case patterns.
+fn case_pattern(name: &str) -> String {
+    if name.contains(|c| "*?[]|()".contains(c)) {
+        format!("'{}'", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+fn option_names_pattern(program_option: &ProgramOption) -> String {
+    program_option
+        .names()
+        .iter()
+        .map(|name| case_pattern(name))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn format_position_cases(cfg: &Config, program_option: &ProgramOption, arity: Arity) -> String {
+    let names = program_option.names();
+    (0..arity.max)
+        .filter_map(|position| {
+            let arg = program_option.arg_at(position);
+            if arg.spec == ValueSpec::Any {
+                return None;
+            }
+
+            let fixed_values_var = if position < program_option.args.len() {
+                get_fixed_values_var_name_for_arg(program_option, position)
+            } else {
                 get_fixed_values_var_name_for_option(program_option)
+            };
+
+            let pattern = names
+                .iter()
+                .map(|name| case_pattern(&format!("{}:{}", name, position + 1)))
+                .collect::<Vec<_>>()
+                .join("|");
+
+            Some(format!(
+                "\t{}){}",
+                pattern,
+                format_compreply(&compgen_words(
+                    cfg,
+                    &arg.spec,
+                    &format!("${{{}}}", fixed_values_var),
+                    "${current}"
+                ))
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_position_lookup(cfg: &Config) -> String {
+    let options_with_arity = cfg
+        .program_options
+        .iter()
+        .filter_map(|o| o.arity.map(|arity| (o, arity)))
+        .collect::<Vec<_>>();
+
+    if options_with_arity.is_empty() {
+        return String::new();
+    }
+
+    let max_arity = options_with_arity
+        .iter()
+        .map(|(_, arity)| arity.max)
+        .max()
+        .unwrap_or(1);
+
+    let names = options_with_arity
+        .iter()
+        .map(|(o, _)| option_names_pattern(o))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    let cases = options_with_arity
+        .iter()
+        .map(|(o, arity)| format_position_cases(cfg, o, *arity))
+        .collect::<Vec<_>>()
+        .join("");
+
+    format!(
+        r#"
+    local option="" position=0 i
+    for (( i = COMP_CWORD - 1; i > 0 && i >= COMP_CWORD - {max_arity}; i-- )); do
+        case "${{COMP_WORDS[i]}}" in
+        {names})
+            option=${{COMP_WORDS[i]}}
+            position=$(( COMP_CWORD - i ))
+            break
+            ;;
+        esac
+    done
+
+    case "${{option}}:${{position}}" in
+    {cases}
+    esac
+"#,
+        max_arity = max_arity,
+        names = names,
+        cases = cases
+    )
+}
+
+fn format_value_list_case(cfg: &Config, program_option: &ProgramOption) -> String {
+    let separator = program_option.value_separator.replace('"', "\\\"");
+    let complete_elements = if !program_option.suboptions.is_empty() {
+        let keys = program_option
+            .suboptions
+            .iter()
+            .map(|suboption| {
+                if suboption.spec == ValueSpec::Any {
+                    suboption.name.clone()
+                } else {
+                    format!("{}=", suboption.name)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let value_cases = program_option
+            .suboptions
+            .iter()
+            .filter(|suboption| suboption.spec != ValueSpec::Any)
+            .map(|suboption| {
+                let fixed_values = match &suboption.spec {
+                    ValueSpec::Fixed(values) => values.join(" "),
+                    _ => String::new(),
+                };
+                format!(
+                    r#"
+                {key})
+                    COMPREPLY=( {words} )
+                    {prefix}
+                    ;;"#,
+                    key = suboption.name,
+                    words = compgen_words(
+                        cfg,
+                        &suboption.spec,
+                        &fixed_values,
+                        "${list_element#*=}"
+                    ),
+                    prefix = format_prefix_compreply("${list_prefix}${key}=")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        format!(
+            r#"
+            if [[ ${{list_element}} == *=* ]]; then
+                local key=${{list_element%%=*}}
+                case "${{key}}" in{value_cases}
+                esac
+            else
+                for value in {keys}; do
+                    [[ "{sep}${{list_prefix}}" == *"{sep}${{value%=}}"[="{sep}"]* ]] || candidates+="${{value}} "
+                done
+                COMPREPLY=( $(compgen -P "${{list_prefix}}" -W "${{candidates}}" -- "${{list_element}}") )
+            fi"#,
+            value_cases = value_cases,
+            keys = keys,
+            sep = separator
+        )
+    } else {
+        let skip_used_values = |values: &str| {
+            format!(
+                r#"
+            for value in {values}; do
+                [[ "{sep}${{list_prefix}}" == *"{sep}${{value}}{sep}"* ]] || candidates+="${{value}} "
+            done
+            COMPREPLY=( $(compgen -P "${{list_prefix}}" -W "${{candidates}}" -- "${{list_element}}") )"#,
+                values = values,
+                sep = separator
             )
-        } else {
-            String::new()
+        };
+
+        match program_option.value_spec() {
+            ValueSpec::Fixed(_) => skip_used_values(&format!(
+                "${{{}}}",
+                get_fixed_values_var_name_for_option(program_option)
+            )),
+            ValueSpec::Named(name)
+                if matches!(
+                    cfg.value_type(&name).map(|t| &t.spec),
+                    Some(ValueSpec::Fixed(_)) | Some(ValueSpec::Command(_))
+                ) =>
+            {
+                skip_used_values(&format!(
+                    r#"$({} "")"#,
+                    value_type_function_name(cfg, &name)
+                ))
+            }
+            spec => format!(
+                r#"
+            COMPREPLY=( {} )
+            {}"#,
+                compgen_words(cfg, &spec, "", "${list_element}"),
+                format_prefix_compreply("${list_prefix}")
+            ),
         }
-    );
+    };
 
-    let short_case = if !program_option.short.is_empty() {
-        format!("\t{})\n{}", program_option.short, compreply)
-    } else {
+    format!(
+        r#"
+	{names})
+            if [[ ${{list_current}} == *"{sep}"* ]]; then
+                list_prefix=${{list_current%"{sep}"*}}"{sep}"
+                list_element=${{list_current##*"{sep}"}}
+            fi
+{complete_elements}
+            COMPREPLY=( "${{COMPREPLY[@]#"${{list_strip}}"}}" )
+            compopt -o nospace 2>/dev/null
+            return 0
+            ;;"#,
+        names = option_names_pattern(program_option),
+        sep = separator,
+        complete_elements = complete_elements
+    )
+}
+
+fn format_value_list_lookup(cfg: &Config) -> String {
+    let cases = cfg
+        .program_options
+        .iter()
+        .filter(|o| o.takes_value_list())
+        .map(|o| format_value_list_case(cfg, o))
+        .collect::<Vec<_>>()
+        .join("");
+
+    if cases.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+    local line=${{COMP_LINE:0:COMP_POINT}}
+    local list_current=${{line##*[[:space:]]}}
+    local list_words=( ${{line%"${{list_current}}"}} )
+    local list_strip=${{list_current%"${{current}}"}}
+    local list_prefix="" list_element=${{list_current}} value candidates=""
+
+    case "${{list_words[${{#list_words[@]}}-1]}}" in{cases}
+    esac
+"#,
+        cases = cases
+    )
+}
+
+fn format_attached_value_lookup(cfg: &Config) -> String {
+    let mut attached_options = cfg
+        .program_options
+        .iter()
+        .filter(|o| o.attached_value != AttachedValue::No && o.value_spec() != ValueSpec::Any)
+        .collect::<Vec<_>>();
+
+    if attached_options.is_empty() {
+        return String::new();
+    }
+
+    // Longer names go first so that e.g -Xmx is not mistaken for -X with value mx.
+    attached_options.sort_by_key(|o| std::cmp::Reverse(o.short.len()));
+
+    // Names that an attached option is a prefix of (e.g -output for -o) are options of their
+    // own, so they are matched before any attached value.
+    let shadowed_names = cfg
+        .program_options
+        .iter()
+        .flat_map(|o| {
+            o.names()
+                .into_iter()
+                .map(|name| name.to_owned())
+                .chain(o.negated().map(|negated| negated.long))
+        })
+        .filter(|name| {
+            attached_options
+                .iter()
+                .any(|o| name.len() > o.short.len() && name.starts_with(o.short.as_str()))
+        })
+        .map(|name| format!("{0}|{0}=*", case_pattern(&name)))
+        .collect::<Vec<_>>();
+
+    let shadowed_case = if shadowed_names.is_empty() {
         String::new()
+    } else {
+        format!(
+            r#"
+    {names})
+        ;;"#,
+            names = shadowed_names.join("|")
+        )
     };
 
-    let long_case = if !program_option.long.is_empty() {
-        format!("\t{})\n{}", program_option.long, compreply)
+    let cases = attached_options
+        .iter()
+        .map(|o| {
+            format!(
+                r#"
+    {pattern}*)
+        COMPREPLY=( {words} )
+        {prefix}
+        return 0
+        ;;"#,
+                pattern = case_pattern(&o.short),
+                words = compgen_words(
+                    cfg,
+                    &o.value_spec(),
+                    &format!("${{{}}}", get_fixed_values_var_name_for_option(o)),
+                    &format!("${{current#{}}}", o.short)
+                ),
+                prefix = format_prefix_compreply(&o.short)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("");
+
+    format!(
+        r#"
+    case "${{current}}" in{shadowed_case}{cases}
+    esac
+"#,
+        shadowed_case = shadowed_case,
+        cases = cases
+    )
+}
+
+fn format_command_line_delegation(cfg: &Config) -> String {
+    let cases = cfg
+        .program_options
+        .iter()
+        .filter(|o| o.delegates_command_line())
+        .map(|o| {
+            let delegate = format!(
+                r#"{function_name}_delegate $(( i + 1 ))
+            return 0"#,
+                function_name = cfg.function_name()
+            );
+
+            if o.terminator.is_empty() {
+                format!(
+                    r#"
+        {names})
+            {delegate}
+            ;;"#,
+                    names = option_names_pattern(o),
+                    delegate = delegate
+                )
+            } else {
+                format!(
+                    r#"
+        {names})
+            for (( j = i + 1; j < COMP_CWORD; j++ )); do
+                [[ ${{COMP_WORDS[j]}} == '{terminator}' || ${{COMP_WORDS[j]}} == '\{terminator}' ]] && break
+            done
+            if (( j == COMP_CWORD )); then
+                {delegate}
+            fi
+            i=${{j}}
+            ;;"#,
+                    names = option_names_pattern(o),
+                    terminator = o.terminator,
+                    delegate = delegate.replace("\n", "\n    ")
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("");
+
+    let cases = if cfg.command_line_after.is_empty() {
+        cases
     } else {
-        String::new()
+        format!(
+            r#"{cases}
+        {words})
+            {function_name}_delegate $(( i + 1 ))
+            return 0
+            ;;"#,
+            cases = cases,
+            words = cfg
+                .command_line_after
+                .iter()
+                .map(|word| case_pattern(word))
+                .collect::<Vec<_>>()
+                .join("|"),
+            function_name = cfg.function_name()
+        )
     };
 
-    format!("{}\n\t{}", short_case, long_case)
+    if cases.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+    local i j
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        case "${{COMP_WORDS[i]}}" in{cases}
+        esac
+    done
+"#,
+        cases = cases
+    )
+}
+
+/// Completes the command starting at the given word, using bash-completion's
+/// _command_offset when it is available and plain command/file names otherwise.
+fn format_delegate_function(cfg: &Config) -> String {
+    if !cfg.delegates_command_line() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+{function_name}_delegate() {{
+    local offset=$1
+    if declare -F _command_offset >/dev/null; then
+        _command_offset "${{offset}}"
+        return
+    fi
+
+    local current=${{COMP_WORDS[COMP_CWORD]}}
+    if (( COMP_CWORD == offset )); then
+        COMPREPLY=( $(compgen -c -- "${{current}}") )
+    else
+        COMPREPLY=( $(compgen -f -- "${{current}}") )
+    fi
+}}
+"#,
+        function_name = cfg.function_name()
+    )
+}
+
+/// Finds the first word that is neither an option nor an option's value and,
+/// if there is one, completes the rest of the line as that external subcommand.
+fn format_external_subcommand_lookup(cfg: &Config) -> String {
+    if cfg.external_subcommands.is_empty() {
+        return String::new();
+    }
+
+    let value_cases = cfg
+        .program_options
+        .iter()
+        .filter(|o| o.accepts_value && o.attached_value != AttachedValue::Required)
+        .map(|o| {
+            if !o.delegates_command_line() {
+                let values = o.arity.map_or(1, |arity| std::cmp::max(arity.min, 1));
+                // bash makes `=` and the value glued to an option (--opt=value) words of their own.
+                format!(
+                    r#"
+        {names})
+            [[ ${{COMP_WORDS[k+1]}} == = ]] && (( k++ ))
+            (( k += {values} ))
+            ;;"#,
+                    names = option_names_pattern(o),
+                    values = values
+                )
+            } else if o.terminator.is_empty() {
+                // The rest of the line belongs to the command.
+                format!(
+                    r#"
+        {names})
+            break
+            ;;"#,
+                    names = option_names_pattern(o)
+                )
+            } else {
+                // The words up to the terminator belong to the command.
+                format!(
+                    r#"
+        {names})
+            for (( k++; k < COMP_CWORD; k++ )); do
+                [[ ${{COMP_WORDS[k]}} == '{terminator}' || ${{COMP_WORDS[k]}} == '\{terminator}' ]] && break
+            done
+            ;;"#,
+                    names = option_names_pattern(o),
+                    terminator = o.terminator
+                )
+            }
+        })
+        .chain(cfg.command_line_after.iter().map(|word| {
+            format!(
+                r#"
+        {word})
+            break
+            ;;"#,
+                word = case_pattern(word)
+            )
+        }))
+        .collect::<Vec<_>>()
+        .join("");
+
+    format!(
+        r#"
+    local k
+    for (( k = 1; k < COMP_CWORD; k++ )); do
+        case "${{COMP_WORDS[k]}}" in{value_cases}
+        =)
+            (( k++ ))
+            ;;
+        [-+]*)
+            ;;
+        *)
+            {function_name}_external_subcommand "${{k}}"
+            return 0
+            ;;
+        esac
+    done
+"#,
+        value_cases = value_cases,
+        function_name = cfg.function_name()
+    )
+}
+
+/// Runs the completion function registered for `<prefix><subcommand>`, loading it through
+/// bash-completion when it is available, and falls back to file names otherwise.
+fn format_external_subcommand_function(cfg: &Config) -> String {
+    if cfg.external_subcommands.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+{function_name}_external_subcommand() {{
+    local index=$1
+    local command="{prefix}${{COMP_WORDS[index]}}"
+    local spec
+    spec=$(complete -p "${{command}}" 2>/dev/null)
+    if [[ -z ${{spec}} ]] && declare -F __load_completion >/dev/null; then
+        __load_completion "${{command}}" && spec=$(complete -p "${{command}}" 2>/dev/null)
+    fi
+
+    if [[ ${{spec}} =~ -F\ ([^ ]+) ]]; then
+        local function=${{BASH_REMATCH[1]}}
+        local line=${{COMP_LINE:0:COMP_POINT}}
+        line=${{line#*[[:space:]]"${{COMP_WORDS[index]}}"}}
+        COMP_LINE="${{command}}${{line}}"
+        COMP_POINT=${{#COMP_LINE}}
+        COMP_WORDS=( "${{command}}" "${{COMP_WORDS[@]:index+1}}" )
+        COMP_CWORD=$(( COMP_CWORD - index ))
+        "${{function}}" "${{command}}" "${{COMP_WORDS[COMP_CWORD]}}" "${{COMP_WORDS[COMP_CWORD-1]}}"
+    else
+        COMPREPLY=( $(compgen -f -- "${{COMP_WORDS[COMP_CWORD]}}") )
+    fi
+}}
+"#,
+        function_name = cfg.function_name(),
+        prefix = cfg.external_subcommands
+    )
+}
+
+fn format_exclusions(cfg: &Config) -> String {
+    let cases = cfg
+        .program_options
+        .iter()
+        .filter_map(|o| {
+            o.negated().map(|negated| {
+                let mut names = o.names();
+                names.push(&negated.long);
+                format!(
+                    r#"
+        {pattern})
+            excluded+="{excluded} "
+            ;;"#,
+                    pattern = names
+                        .iter()
+                        .map(|name| case_pattern(name))
+                        .collect::<Vec<_>>()
+                        .join("|"),
+                    excluded = names.join(" ")
+                )
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("");
+
+    if cases.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        r#"
+    local word excluded=" "
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        case "${{word}}" in{cases}
+        esac
+    done
+"#,
+        cases = cases
+    )
+}
+
+/// Puts a header line before the options of each category when all options are listed,
+/// which keeps the candidates in their listed order. The grouping of the whole listing
+/// is kept, since it is what a TAB on an empty word offers.
+///
+/// bash has no way to show a line that can't be inserted, so the headers are only added when
+/// it lists the candidates on a second TAB (COMP_TYPE 63). Menu completion, show-all-if-ambiguous
+/// and the insertion of the common prefix never see them. bash older than 4.4 would sort the
+/// headers away from their options, so it lists the options ungrouped.
+fn format_category_headers(cfg: &Config, listed_options: &[ProgramOption], listed: usize) -> String {
+    let categories = listed_options
+        .iter()
+        .filter(|o| !o.category.is_empty())
+        .flat_map(|o| {
+            vec![&o.short, &o.long]
+                .into_iter()
+                .filter(|name| !name.is_empty())
+                .map(move |name| {
+                    format!(
+                        "['{}']='{}'",
+                        name.replace('\'', "'\\''"),
+                        o.category.replace('\'', "'\\''")
+                    )
+                })
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        r#"
+    if (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 4) )) &&
+        [[ ${{COMP_TYPE}} == 63 && -z ${{current//[-+]/}} && ${{#COMPREPLY[@]}} -gt 1 ]]; then
+        if (( ${{#COMPREPLY[@]}} == {listed} && ${{#{function_name}_grouped[@]}} )); then
+            COMPREPLY=( "${{{function_name}_grouped[@]}}" )
+        else
+            (( ${{#{function_name}_categories[@]}} )) || declare -gA {function_name}_categories=( {categories} )
+            local entry category="" grouped=()
+            for entry in "${{COMPREPLY[@]}}"; do
+                if [[ -n ${{{function_name}_categories[${{entry%% *}}]}} && ${{{function_name}_categories[${{entry%% *}}]}} != "${{category}}" ]]; then
+                    category=${{{function_name}_categories[${{entry%% *}}]}}
+                    grouped+=( "${{category}}:" )
+                fi
+                grouped+=( "${{entry}}" )
+            done
+            (( ${{#COMPREPLY[@]}} == {listed} )) && {function_name}_grouped=( "${{grouped[@]}}" )
+            COMPREPLY=( "${{grouped[@]}}" )
+        fi
+        compopt -o nosort 2>/dev/null
+    fi"#,
+        function_name = cfg.function_name(),
+        listed = listed,
+        categories = categories
+    )
 }
 
 pub fn format_option(max_opt_len: usize, program_option: &ProgramOption) -> String {
@@ -80,12 +1083,49 @@ pub fn format_option(max_opt_len: usize, program_option: &ProgramOption) -> Stri
 }
 
 pub fn generate_bash(cfg: &Config) -> String {
-    let any_with_description = cfg
+    let listed_options = cfg
         .program_options
         .iter()
-        .any(|o| !o.description.is_empty());
+        .filter(|o| !o.hidden)
+        .flat_map(|o| {
+            let description = o.listed_description();
+            let aliases = o
+                .aliases
+                .iter()
+                .filter(move |_| !o.hide_aliases)
+                .map(move |alias| ProgramOption {
+                    long: alias.clone(),
+                    description: o.listed_description(),
+                    category: o.category.clone(),
+                    ..ProgramOption::default()
+                });
+
+            let negated = o.negated().map(|negated| ProgramOption {
+                category: o.category.clone(),
+                ..negated
+            });
+
+            std::iter::once(ProgramOption {
+                description,
+                ..o.clone()
+            })
+            .chain(aliases)
+            .chain(negated)
+        })
+        .collect::<Vec<_>>();
+    let any_with_description = listed_options.iter().any(|o| !o.description.is_empty());
+
+    let categories = cfg.categories();
+    let mut listed_options = listed_options;
+    listed_options.sort_by_key(|o| {
+        categories
+            .iter()
+            .position(|category| *category == o.category)
+            .map_or(0, |position| position + 1)
+    });
+
     let max_option_len = if any_with_description {
-        cfg.program_options.iter().fold(0, |curr_max: usize, o| {
+        listed_options.iter().fold(0, |curr_max: usize, o| {
             let opt = if o.long.is_empty() { &o.short } else { &o.long };
             std::cmp::max(curr_max, opt.len())
         })
@@ -93,8 +1133,7 @@ pub fn generate_bash(cfg: &Config) -> String {
         0
     };
 
-    let opts = cfg
-        .program_options
+    let opts = listed_options
         .iter()
         .map(|o| format_option(max_option_len, o))
         .collect::<Vec<_>>()
@@ -106,18 +1145,15 @@ pub fn generate_bash(cfg: &Config) -> String {
         opts
     };
 
-    let cases = cfg
-        .program_options
-        .iter()
-        .filter(|o| o.accepts_files || !o.fixed_values.is_empty())
-        .map(|o| format_option_cases(o))
-        .collect::<Vec<_>>()
-        .join("");
-
     let fixed_value_vars = cfg
         .program_options
         .iter()
-        .filter(|o| !o.fixed_values.is_empty())
+        .filter(|o| {
+            !o.fixed_values.is_empty()
+                && (o.arity.is_some()
+                    || o.takes_value_list()
+                    || o.attached_value != AttachedValue::No)
+        })
         .map(|o| {
             format!(
                 r#"local {fixed_values_var}="{fixed_values}""#,
@@ -125,11 +1161,77 @@ pub fn generate_bash(cfg: &Config) -> String {
                 fixed_values = o.fixed_values.join(" ")
             )
         })
+        .chain(cfg.program_options.iter().flat_map(|o| {
+            o.args
+                .iter()
+                .enumerate()
+                .filter_map(move |(position, arg)| match &arg.spec {
+                    ValueSpec::Fixed(values) => Some(format!(
+                        r#"local {fixed_values_var}="{fixed_values}""#,
+                        fixed_values_var = get_fixed_values_var_name_for_arg(o, position),
+                        fixed_values = values.join(" ")
+                    )),
+                    _ => None,
+                })
+        }))
         .collect::<Vec<_>>()
         .join("\n\t");
 
-    let complete_current =
-        r#"COMPREPLY=( $(compgen -W "${{opts}}" -- "${{current}}") )"#.to_string();
+    let complete_value_function = format_complete_value_function(cfg);
+
+    let command_line_delegation = format_command_line_delegation(cfg);
+    let delegate_function = format_delegate_function(cfg);
+    let exclusions = format_exclusions(cfg);
+    let external_subcommand_lookup = format_external_subcommand_lookup(cfg);
+    let external_subcommand_function = format_external_subcommand_function(cfg);
+    let attached_value_lookup = format_attached_value_lookup(cfg);
+    let value_type_functions = format_value_type_functions(cfg);
+    let option_value_function = format_option_value_function(cfg);
+    let cache_function = format_cache_function(cfg);
+    let env_names_function = format_env_names_function(cfg);
+
+    let any_plus_prefixed = cfg
+        .program_options
+        .iter()
+        .any(|o| o.names().iter().any(|name| name.starts_with('+')));
+    let option_prefixes = if any_plus_prefixed { "[-+]" } else { "-" };
+
+    let complete_current = if exclusions.is_empty() {
+        r#"COMPREPLY=( $(compgen -W "${opts}" -- "${current}") )"#.to_string()
+    } else {
+        r#"COMPREPLY=( $(compgen -W "${opts}" -- "${current}") )
+    local entry filtered=()
+    for entry in "${COMPREPLY[@]}"; do
+        [[ ${excluded} == *" ${entry%% *} "* ]] || filtered+=( "${entry}" )
+    done
+    COMPREPLY=( "${filtered[@]}" )"#
+            .to_string()
+    };
+
+    let complete_current = if cfg.external_subcommands.is_empty() {
+        complete_current
+    } else {
+        format!(
+            r#"{complete_current}
+    if [[ ${{current}} != {option_prefixes}* ]]; then
+        local subcommands=( $(compgen -c -- "{prefix}${{current}}" | sort -u) )
+        COMPREPLY+=( "${{subcommands[@]#"{prefix}"}}" )
+    fi"#,
+            complete_current = complete_current,
+            option_prefixes = option_prefixes,
+            prefix = cfg.external_subcommands
+        )
+    };
+
+    let complete_current = if any_with_description && !categories.is_empty() {
+        format!(
+            "{}{}",
+            complete_current,
+            format_category_headers(cfg, &listed_options, opts.trim_start().lines().count())
+        )
+    } else {
+        complete_current
+    };
 
     let ifs_change = if any_with_description {
         r#"
@@ -156,44 +1258,767 @@ pub fn generate_bash(cfg: &Config) -> String {
 
     std::format!(
         r##"#!/usr/bin/bash
-_{prog_name}_completions() {{
+{function_name}_completions() {{
     COMPREPLY=()
     local current=${{COMP_WORDS[COMP_CWORD]}}
     local previous=${{COMP_WORDS[COMP_CWORD-1]}}
     local opts="{opts}"
     {fixed_value_vars}
-
-    if [[ ${{current}} == -* || ${{COMP_CWORD}} -eq 1 ]]; then
-        {ifs_change}
-        {complete_current}
-        {ifs_restore}
-        {truncate_description}
+{command_line_delegation}{external_subcommand_lookup}{exclusions}{attached_value_lookup}
+    if [[ ${{current}} != {option_prefixes}* && ${{COMP_CWORD}} -ne 1 ]] && {function_name}_complete_value; then
         return 0
     fi
 
-    case "${{previous}}" in
-    {cases}
-
-        *)
-            COMPREPLY=()
-            ;;
-    esac
-
     {ifs_change}
     {complete_current}
     {ifs_restore}
     {truncate_description}
     return 0
 }}
-
-complete -F _{prog_name}_completions -o bashdefault -o default {prog_name}"##,
-        prog_name = cfg.program_name,
+unset -v {function_name}_values {function_name}_value_code {function_name}_categories {function_name}_grouped
+{complete_value_function}{value_type_functions}{option_value_function}{cache_function}{delegate_function}{external_subcommand_function}
+complete -F {function_name}_completions -o bashdefault -o default {command_names}{env_names_function}"##,
+        function_name = cfg.function_name(),
+        command_names = cfg.command_names().join(" "),
         opts = opts,
-        cases = cases,
+        complete_value_function = complete_value_function,
         complete_current = complete_current,
+        command_line_delegation = command_line_delegation,
+        value_type_functions = value_type_functions,
+        option_value_function = option_value_function,
+        cache_function = cache_function,
+        env_names_function = env_names_function,
+        delegate_function = delegate_function,
+        external_subcommand_lookup = external_subcommand_lookup,
+        external_subcommand_function = external_subcommand_function,
+        exclusions = exclusions,
+        attached_value_lookup = attached_value_lookup,
+        option_prefixes = option_prefixes,
         fixed_value_vars = fixed_value_vars,
         ifs_change = ifs_change,
         ifs_restore = ifs_restore,
         truncate_description = truncate_description
     )
 }
+
+/// Generates a script that leaves the completion to `autocshell complete`. The words bash split
+/// at characters like `=` are joined again where no whitespace separates them on the line, and
+/// the part of the completed word that bash doesn't consider part of the current word is removed
+/// from the candidates.
+pub fn generate_bash_runtime(cfg: &Config, spec_file: &str) -> String {
+    format!(
+        r#"#!/usr/bin/bash
+{function_name}_completions() {{
+    # Puts back together the words bash split at = and :, going by the line up to the cursor.
+    local line=${{COMP_LINE:0:COMP_POINT}} words=() strip= space piece i
+    for (( i = 0; i <= COMP_CWORD; i++ )); do
+        space=${{line%%[![:space:]]*}}
+        line=${{line:${{#space}}}}
+        piece=${{line}}
+        (( i < COMP_CWORD )) && piece=${{line:0:${{#COMP_WORDS[i]}}}}
+        line=${{line:${{#piece}}}}
+        if (( i > 0 )) && [[ -z ${{space}} ]]; then
+            strip=${{words[${{#words[@]}}-1]}}
+            words[${{#words[@]}}-1]+=${{piece}}
+        else
+            strip=
+            words+=( "${{piece}}" )
+        fi
+    done
+
+    local IFS=$'\n'
+    local candidates=( $(autocshell complete --spec '{spec_file}' -- "${{words[@]}}" 2>/dev/null) )
+
+    if [[ ${{#candidates[@]}} -eq 1 ]]; then
+        COMPREPLY=( "${{candidates[0]%%$'\t'*}}" )
+        [[ ${{COMPREPLY[0]}} == */ || ${{COMPREPLY[0]}} == *= ]] && compopt -o nospace
+    else
+        COMPREPLY=( "${{candidates[@]/$'\t'/ -- }}" )
+        COMPREPLY=( "${{COMPREPLY[@]% -- }}" )
+    fi
+    COMPREPLY=( "${{COMPREPLY[@]#"${{strip}}"}}" )
+    return 0
+}}
+
+complete -F {function_name}_completions {command_names}"#,
+        function_name = cfg.function_name(),
+        spec_file = spec_file.replace('\'', "'\\''"),
+        command_names = cfg.command_names().join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    fn script(config: &str) -> String {
+        generate_bash(&Config::from_string(config).unwrap())
+    }
+
+    // Creates an empty directory for the test `name`, unique to this run of the tests.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("autocshell_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Loads `script` and completes `line` the way bash does on a TAB at its end: the words are
+    // split at whitespace and at the `=` and `:` word breaks, and COMP_TYPE is `comp_type`. The
+    // completion runs in `dir`, which also comes first in PATH. Returns COMPREPLY.
+    fn run_completion(script: &str, line: &str, comp_type: u8, dir: &Path) -> Vec<String> {
+        let driver = r#"
+            compopt() { :; }
+            COMP_LINE=$1 COMP_POINT=${#1} COMP_TYPE=$2
+            COMP_WORDS=()
+            word=
+            for (( i = 0; i < ${#COMP_LINE}; i++ )); do
+                c=${COMP_LINE:i:1}
+                case ${c} in
+                [[:space:]])
+                    [[ -n ${word} ]] && COMP_WORDS+=( "${word}" )
+                    word=
+                    ;;
+                [=:])
+                    [[ -n ${word} ]] && COMP_WORDS+=( "${word}" )
+                    COMP_WORDS+=( "${c}" )
+                    word=
+                    ;;
+                *)
+                    word+=${c}
+                    ;;
+                esac
+            done
+            [[ -n ${word} || ${COMP_LINE} == *[[:space:]] ]] && COMP_WORDS+=( "${word}" )
+            COMP_CWORD=$(( ${#COMP_WORDS[@]} - 1 ))
+
+            # Like bash, fall back to the completion of the last component of a path.
+            spec=$(complete -p "${COMP_WORDS[0]}" 2>/dev/null || complete -p "${COMP_WORDS[0]##*/}")
+            function=${spec#*-F }
+            "${function%% *}" "${COMP_WORDS[0]}" "${COMP_WORDS[COMP_CWORD]}" \
+                "${COMP_WORDS[COMP_CWORD-1]}"
+            (( ${#COMPREPLY[@]} )) && printf '%s\n' "${COMPREPLY[@]}"
+            exit 0
+        "#;
+
+        let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\n{}", script, driver))
+            .arg("bash")
+            .arg(line)
+            .arg(comp_type.to_string())
+            .current_dir(dir)
+            .env("PATH", path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    // Completes `line` with the script generated from `config` on a first TAB.
+    fn complete(config: &str, line: &str) -> Vec<String> {
+        run_completion(&script(config), line, 9, Path::new("."))
+    }
+
+    // Runs the completion function of the runtime script with a stub `autocshell` that prints
+    // `candidates` and records its arguments. Returns the arguments and the resulting COMPREPLY.
+    fn run_runtime(line: &str, comp_words: &[&str], candidates: &str) -> (String, String) {
+        let dir = test_dir("runtime");
+        let stub = dir.join("autocshell");
+        fs::write(
+            &stub,
+            r#"#!/bin/sh
+printf '%s\n' "$@" > "$(dirname "$0")/args"
+printf '%b' "$CANDIDATES"
+"#,
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cfg = Config::from_string("program_name: prog\noption:\n    long: --color\n").unwrap();
+        let script = generate_bash_runtime(&cfg, "/specs/it's.cfg");
+        let driver = r#"
+            compopt() { echo compopt "$@"; }
+            COMP_LINE=$1 COMP_POINT=${#1}
+            shift
+            COMP_WORDS=( "$@" ) COMP_CWORD=$(( $# - 1 ))
+            _prog_completions prog
+            printf '[%s]\n' "${COMPREPLY[@]}"
+        "#;
+
+        let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\n{}", script, driver))
+            .arg("bash")
+            .arg(line)
+            .args(comp_words)
+            .env("PATH", path)
+            .env("CANDIDATES", candidates)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let args = fs::read_to_string(dir.join("args")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (args, String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn runtime_scripts_pass_the_words_through() {
+        let (args, reply) = run_runtime(
+            "prog --color=a",
+            &["prog", "--color", "=", "a"],
+            "--color=auto\\tAutomatic\\n--color=always\\t\\n",
+        );
+        assert_eq!(args, "complete\n--spec\n/specs/it's.cfg\n--\nprog\n--color=a\n");
+        assert_eq!(reply, "[auto -- Automatic]\n[always]\n");
+
+        let (args, reply) = run_runtime("prog ", &["prog", ""], "dir/\\tA directory\\n");
+        assert_eq!(args, "complete\n--spec\n/specs/it's.cfg\n--\nprog\n\n");
+        assert_eq!(reply, "compopt -o nospace\n[dir/]\n");
+
+        let (args, reply) = run_runtime(
+            "prog --name 'a b' --c",
+            &["prog", "--name", "'a b'", "--c"],
+            "--color\\t\\n",
+        );
+        assert_eq!(args, "complete\n--spec\n/specs/it's.cfg\n--\nprog\n--name\n'a b'\n--c\n");
+        assert_eq!(reply, "[--color]\n");
+
+        let (args, reply) = run_runtime(
+            "prog --color = a",
+            &["prog", "--color", "=", "a"],
+            "auto\\t\\n",
+        );
+        assert_eq!(args, "complete\n--spec\n/specs/it's.cfg\n--\nprog\n--color\n=\na\n");
+        assert_eq!(reply, "[auto]\n");
+    }
+
+    #[test]
+    fn value_lists_and_suboptions_are_completed() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --features
+                value_separator: ,
+                fixed_values: [serde, derive, std]
+            option:
+                short: -o
+                suboption: ro
+                suboption: mode [fast, safe]
+                suboption: uid
+        ";
+
+        assert_eq!(complete(config, "prog --features "), ["serde", "derive", "std"]);
+        assert_eq!(complete(config, "prog --features serde,"), ["serde,derive", "serde,std"]);
+        assert_eq!(complete(config, "prog --features serde,s"), ["serde,std"]);
+        assert_eq!(complete(config, "prog -o "), ["ro", "mode=", "uid"]);
+        assert_eq!(complete(config, "prog -o ro,"), ["ro,mode=", "ro,uid"]);
+        assert_eq!(complete(config, "prog -o mode="), ["=fast", "=safe"]);
+        assert_eq!(complete(config, "prog -o ro,mode=s"), ["safe"]);
+        assert_eq!(complete(config, "prog -o ro,mode=fast,"), ["fast,uid"]);
+    }
+
+    #[test]
+    fn negated_flags_are_listed_and_exclude_each_other() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --color
+                negatable: true
+            option:
+                long: --verbose
+        ";
+
+        assert_eq!(
+            complete(config, "prog --"),
+            ["--color", "--no-color -- Negate --color", "--verbose"]
+        );
+        assert_eq!(complete(config, "prog --no"), ["--no-color"]);
+        assert_eq!(complete(config, "prog --color --"), ["--verbose"]);
+        assert_eq!(complete(config, "prog --no-color --"), ["--verbose"]);
+    }
+
+    #[test]
+    fn aliases_are_listed_and_complete_values() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --verbose
+                aliases: [-v, --loud]
+            option:
+                long: --output
+                aliases: [-O]
+                hide_aliases: true
+                fixed_values: [json, text]
+        ";
+
+        assert_eq!(complete(config, "prog -"), ["--verbose", "-v", "--loud", "--output"]);
+        assert_eq!(complete(config, "prog --lo"), ["--loud"]);
+        assert_eq!(complete(config, "prog -O "), ["json", "text"]);
+        assert_eq!(complete(config, "prog --output t"), ["text"]);
+    }
+
+    #[test]
+    fn single_dash_and_plus_prefixed_options_are_completed() {
+        let config = "\
+            program_name: prog
+            option:
+                long: -name
+                fixed_values: [a, b]
+            option:
+                short: +x
+            option:
+                short: -x
+            option:
+                long: --all
+        ";
+
+        assert_eq!(complete(config, "prog -"), ["-name", "-x", "--all"]);
+        assert_eq!(complete(config, "prog +"), ["+x"]);
+        assert_eq!(complete(config, "prog -name "), ["a", "b"]);
+        assert_eq!(complete(config, "prog -name a +"), ["+x"]);
+    }
+
+    #[test]
+    fn attached_values_are_completed() {
+        let config = "\
+            program_name: prog
+            option:
+                short: -O
+                attached_value: only
+                fixed_values: [0, 1, 2, s]
+            option:
+                short: -o
+                long: --output
+                attached_value: true
+                fixed_values: [json, text]
+            option:
+                long: --jobs
+        ";
+
+        assert_eq!(complete(config, "prog -O"), ["-O0", "-O1", "-O2", "-Os"]);
+        assert_eq!(complete(config, "prog -O "), ["-O", "-o", "--output", "--jobs"]);
+        assert_eq!(complete(config, "prog -oj"), ["-ojson"]);
+        assert_eq!(complete(config, "prog -o "), ["json", "text"]);
+        assert_eq!(complete(config, "prog --output t"), ["text"]);
+        assert_eq!(complete(config, "prog -O2 --j"), ["--jobs"]);
+    }
+
+    #[test]
+    fn file_filters_are_applied() {
+        let dir = test_dir("file_filters_test");
+        for subdir in &["sub", ".hidden"] {
+            fs::create_dir(dir.join(subdir)).unwrap();
+        }
+        for file in &["a.toml", "b.txt", ".c.toml", "run.sh"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        fs::set_permissions(dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        let script = script(
+            "\
+            program_name: prog
+            option:
+                long: --config
+                files: *.toml no_hidden
+            option:
+                long: --dir
+                files: dirs
+            option:
+                long: --exe
+                files: executables
+            option:
+                long: --point
+                arg: x files(*.txt)
+                arg: y [up, down]
+            ",
+        );
+
+        // compgen lists the files in directory order.
+        let complete = |line| {
+            let mut candidates = run_completion(&script, line, 9, &dir);
+            candidates.sort();
+            candidates
+        };
+        assert_eq!(complete("prog --config "), ["a.toml", "sub"]);
+        assert_eq!(complete("prog --config ."), Vec::<String>::new());
+        assert_eq!(complete("prog --dir "), [".hidden", "sub"]);
+        assert_eq!(complete("prog --exe "), [".hidden", "run.sh", "sub"]);
+        assert_eq!(complete("prog --point "), [".hidden", "b.txt", "sub"]);
+        assert_eq!(complete("prog --point b.txt "), ["down", "up"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn value_kinds_are_completed() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --signal
+                value_type: signal
+            option:
+                long: --user
+                value_type: user
+            option:
+                long: --cmd
+                value_type: command
+            option:
+                long: --var
+                value_type: env_var
+            option:
+                long: --pair
+                arg: first signal
+                arg: second [a, b]
+        ";
+
+        assert_eq!(complete(config, "prog --signal SIGKI"), ["SIGKILL"]);
+        assert!(complete(config, "prog --user roo").contains(&"root".to_owned()));
+        assert!(complete(config, "prog --cmd bas").contains(&"bash".to_owned()));
+        assert!(complete(config, "prog --var PAT").contains(&"PATH".to_owned()));
+        assert_eq!(complete(config, "prog --pair SIGTER"), ["SIGTERM"]);
+        assert_eq!(complete(config, "prog --pair SIGTERM "), ["a", "b"]);
+    }
+
+    #[test]
+    fn command_lines_are_delegated() {
+        let dir = test_dir("delegation_test");
+        let command = dir.join("mytool");
+        fs::write(&command, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&command, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let script = script(
+            "\
+            program_name: prog
+            command_line_after: [--]
+            option:
+                long: --wrap
+                value_type: command_line
+            option:
+                long: --exec
+                value_type: command_line
+                terminator: ;
+            option:
+                long: --verbose
+            ",
+        );
+
+        let complete = |line| run_completion(&script, line, 9, &dir);
+        assert_eq!(complete("prog --wrap myt"), ["mytool"]);
+        assert_eq!(complete("prog --wrap mytool no"), ["notes.txt"]);
+        assert_eq!(complete("prog -- myt"), ["mytool"]);
+        assert_eq!(complete("prog --exec mytool ; --v"), ["--verbose"]);
+
+        // bash-completion's _command_offset completes the command with its own completion.
+        let script = format!("{}\n_command_offset() {{ COMPREPLY=( \"offset $1\" ); }}", script);
+        let complete = |line| run_completion(&script, line, 9, &dir);
+        assert_eq!(complete("prog --wrap mytool --f"), ["offset 2"]);
+        assert_eq!(complete("prog --verbose --exec mytool --f"), ["offset 3"]);
+        assert_eq!(complete("prog --exec mytool ; -- mytool --f"), ["offset 5"]);
+        assert_eq!(complete("prog --exec mytool ; --wrap mytool --f"), ["offset 5"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn external_subcommands_follow_values_given_with_equals_signs() {
+        let dir = test_dir("external_subcommand_test");
+        let subcommand = dir.join("my.tool-deploy");
+        fs::write(&subcommand, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&subcommand, fs::Permissions::from_mode(0o755)).unwrap();
+        let script = format!(
+            "{}\n_deploy() {{ COMPREPLY=( $(compgen -W '--env --force' -- \"$2\") ); }}
+            complete -F _deploy my.tool-deploy",
+            script(
+                "\
+                program_name: my.tool
+                external_subcommands: my.tool-
+                option:
+                    long: --cluster
+                    fixed_values: [dev, prod]
+                option:
+                    long: --env
+                    fixed_values: [staging, live]
+                option:
+                    short: -O
+                    suboption: uid
+                    suboption: gid
+                "
+            )
+        );
+
+        let complete = |line| run_completion(&script, line, 9, &dir);
+        assert_eq!(complete("my.tool --cluster=dev --env "), ["staging", "live"]);
+        assert_eq!(complete("my.tool --cluster=dev --e"), ["--env"]);
+        assert_eq!(complete("my.tool --cluster=dev dep"), ["deploy"]);
+        assert_eq!(complete("my.tool --cluster=dev deploy --f"), ["--force"]);
+        assert_eq!(complete("my.tool -O uid="), Vec::<String>::new());
+        assert_eq!(complete("my.tool -O uid=0 --c"), ["--cluster"]);
+        assert_eq!(complete("my.tool -O uid=0 deploy --e"), ["--env"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn named_value_types_are_completed() {
+        let config = "\
+            program_name: prog
+            value_type stage:
+                fixed_values: [dev, prod]
+            value_type item:
+                values_command: printf 'x1\\nx2\\n'
+            option:
+                long: --stage
+                value_type: stage
+            option:
+                long: --item
+                value_type: item
+            option:
+                long: --pair
+                arg: a stage
+                arg: b item
+            option:
+                short: -o
+                suboption: stage stage
+        ";
+
+        assert_eq!(complete(config, "prog --stage "), ["dev", "prod"]);
+        assert_eq!(complete(config, "prog --item x"), ["x1", "x2"]);
+        assert_eq!(complete(config, "prog --pair d"), ["dev"]);
+        assert_eq!(complete(config, "prog --pair dev "), ["x1", "x2"]);
+        assert_eq!(complete(config, "prog -o stage="), ["=dev", "=prod"]);
+    }
+
+    #[test]
+    fn values_commands_see_earlier_option_values() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --cluster
+                aliases: [-c]
+                fixed_values: [c1, c2]
+            option:
+                long: --ns
+                values_command: echo \"ns-of-{--cluster}\"
+        ";
+
+        assert_eq!(complete(config, "prog --ns "), ["ns-of-"]);
+        assert_eq!(complete(config, "prog --cluster c2 --ns "), ["ns-of-c2"]);
+        assert_eq!(complete(config, "prog --cluster=c1 --ns "), ["ns-of-c1"]);
+        assert_eq!(complete(config, "prog -c c2 --ns "), ["ns-of-c2"]);
+    }
+
+    #[test]
+    fn values_command_output_is_cached() {
+        let dir = test_dir("cache_test");
+        let script = format!(
+            "XDG_CACHE_HOME={}\n{}",
+            dir.display(),
+            script(
+                "\
+                program_name: prog
+                option:
+                    long: --item
+                    values_command: echo run >> runs; printf 'x1\\nx2\\n'
+                    cache_ttl: 1h
+                "
+            )
+        );
+
+        let complete = |line| run_completion(&script, line, 9, &dir);
+        let runs = || fs::read_to_string(dir.join("runs")).unwrap().lines().count();
+        assert_eq!(complete("prog --item "), ["x1", "x2"]);
+        assert_eq!(complete("prog --item x"), ["x1", "x2"]);
+        assert_eq!(runs(), 1);
+
+        // Once the output is older than the TTL, the command is run again.
+        let cached = fs::read_dir(dir.join("autocshell/prog")).unwrap().next().unwrap().unwrap();
+        let touched = Command::new("touch")
+            .args(["-t", "200001010000"])
+            .arg(cached.path())
+            .status()
+            .unwrap();
+        assert!(touched.success());
+        assert_eq!(complete("prog --item "), ["x1", "x2"]);
+        assert_eq!(runs(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_options_are_not_listed_but_complete_values() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --secret
+                hidden: true
+                fixed_values: [a, b]
+            option:
+                long: --old
+                deprecated: use --new instead
+            option:
+                long: --new
+                description: The new way
+        ";
+
+        assert_eq!(
+            complete(config, "prog --"),
+            ["--old -- deprecated: use --new instead", "--new -- The new way"]
+        );
+        assert_eq!(complete(config, "prog --s"), Vec::<String>::new());
+        assert_eq!(complete(config, "prog --secret "), ["a", "b"]);
+        assert_eq!(complete(config, "prog --o"), ["--old"]);
+    }
+
+    #[test]
+    fn category_headers_are_listed_on_a_second_tab() {
+        let dir = test_dir("categories_test");
+        let script = script(
+            "\
+            program_name: prog
+            option:
+                long: --help
+                description: Show help
+            section: Bob's \"tools\" $(touch pwned)
+            option:
+                long: --build
+                description: Build it
+            option:
+                long: --bench
+                description: Bench it
+            section: Output
+            option:
+                long: --color
+                description: Colour
+            ",
+        );
+
+        let options = [
+            "--help  -- Show help",
+            "--build -- Build it",
+            "--bench -- Bench it",
+            "--color -- Colour",
+        ];
+        assert_eq!(run_completion(&script, "prog --", 9, &dir), options);
+        assert_eq!(run_completion(&script, "prog --", 37, &dir), options);
+        assert_eq!(
+            run_completion(&script, "prog --", 63, &dir),
+            [
+                "--help  -- Show help",
+                "Bob's \"tools\" $(touch pwned):",
+                "--build -- Build it",
+                "--bench -- Bench it",
+                "Output:",
+                "--color -- Colour",
+            ]
+        );
+        assert_eq!(
+            run_completion(&script, "prog --b", 63, &dir),
+            ["--build -- Build it", "--bench -- Bench it"]
+        );
+        assert!(!dir.join("pwned").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn value_hints_are_listed_on_a_second_tab() {
+        let dir = test_dir("hints_test");
+        let script = script(
+            "\
+            program_name: prog
+            option:
+                long: --port
+                value_name: PORT
+                hint: \"TCP port\" $(touch pwned) `touch pwned` $5
+                default: 8080
+            option:
+                long: --name
+                hint: The user's name
+            ",
+        );
+
+        assert_eq!(run_completion(&script, "prog --port ", 9, &dir), Vec::<String>::new());
+        assert_eq!(run_completion(&script, "prog --port ", 37, &dir), Vec::<String>::new());
+        assert_eq!(
+            run_completion(&script, "prog --port ", 63, &dir),
+            [
+                "<PORT>",
+                "\"TCP port\" $(touch pwned) `touch pwned` $5 (default: 8080)",
+            ]
+        );
+        assert_eq!(run_completion(&script, "prog --port 8", 63, &dir), Vec::<String>::new());
+        assert_eq!(
+            run_completion(&script, "prog --name ", 63, &dir),
+            ["<value>", "The user's name"]
+        );
+        assert!(!dir.join("pwned").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn environment_variable_names_are_only_offered_when_asked_for() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --format
+                env: PROG_FORMAT
+            env PROG_HOME:
+                value_type: dir
+        ";
+        assert!(!script(config).contains("complete -I"));
+
+        // Call the function that `complete -I` registers as if it completed a command name.
+        let script = format!(
+            "{}\ncomplete -F _prog_env_names env_names",
+            script(&format!("complete_env_names: true\n{}", config))
+        );
+        let complete = |line| run_completion(&script, line, 9, Path::new("."));
+        assert_eq!(complete("env_names PROG_"), ["PROG_HOME=", "PROG_FORMAT="]);
+        assert_eq!(complete("env_names PROG_F"), ["PROG_FORMAT="]);
+    }
+
+    #[test]
+    fn every_program_name_and_path_is_completed() {
+        let config = "\
+            program_names: [ourtool, ourtool-dev]
+            option:
+                long: --color
+                fixed_values: [auto, never]
+        ";
+
+        assert_eq!(complete(config, "ourtool --c"), ["--color"]);
+        assert_eq!(complete(config, "ourtool-dev --color "), ["auto", "never"]);
+        assert_eq!(complete(config, "./target/debug/ourtool --color n"), ["never"]);
+    }
+
+    #[test]
+    fn programs_with_similar_names_can_be_loaded_together() {
+        let scripts = ["my.tool", "my-tool", "my_tool", "my_2e_tool"]
+            .iter()
+            .map(|name| {
+                script(&format!(
+                    "program_name: {}\noption:\n    long: --name\n    fixed_values: [{}]\n",
+                    name,
+                    name.replace('.', "dot")
+                ))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let complete = |line| run_completion(&scripts, line, 9, Path::new("."));
+        assert_eq!(complete("my.tool --name "), ["mydottool"]);
+        assert_eq!(complete("my-tool --name "), ["my-tool"]);
+        assert_eq!(complete("my_tool --name "), ["my_tool"]);
+        assert_eq!(complete("my_2e_tool --name "), ["my_2e_tool"]);
+    }
+}
+
@@ -0,0 +1,212 @@
+use crate::bash::generate_bash;
+use crate::config::*;
+use crate::zsh::{format_compdef, generate_zsh};
+
+/// A file of a bundle, with its path relative to the bundle directory.
+pub struct BundleFile {
+    pub path: String,
+    pub contents: String,
+}
+
+fn check_function_names(cfgs: &[Config]) -> Result<(), String> {
+    for (i, cfg) in cfgs.iter().enumerate() {
+        if let Some(other) = cfgs[..i]
+            .iter()
+            .find(|other| other.function_name() == cfg.function_name())
+        {
+            return Err(format!(
+                "Programs '{}' and '{}' would share the completion function '{}'",
+                other.program_name,
+                cfg.program_name,
+                cfg.function_name()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The loader is registered for every program and sources the real script of the program on
+/// first use. The script replaces the registration and returning 124 makes bash retry the
+/// completion with it.
+fn bash_bundle(cfgs: &[Config]) -> Vec<BundleFile> {
+    let cases = cfgs
+        .iter()
+        .map(|cfg| {
+            format!(
+                "    {names}) file={function_name}.bash ;;\n",
+                names = cfg.command_names().join("|"),
+                function_name = cfg.function_name()
+            )
+        })
+        .collect::<String>();
+
+    let names = cfgs
+        .iter()
+        .flat_map(|cfg| cfg.command_names())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let stubs = format!(
+        r#"_autocshell_bundle_dir=.
+[[ ${{BASH_SOURCE[0]}} == */* ]] && _autocshell_bundle_dir=${{BASH_SOURCE[0]%/*}}
+[[ ${{_autocshell_bundle_dir}} == /* ]] || _autocshell_bundle_dir=${{PWD}}/${{_autocshell_bundle_dir}}
+
+_autocshell_bundle_load() {{
+    local file
+    case ${{1##*/}} in
+{cases}    *) return 1 ;;
+    esac
+
+    source "${{_autocshell_bundle_dir}}/bash/${{file}}" && return 124
+}}
+
+complete -F _autocshell_bundle_load -o bashdefault -o default {names}
+"#,
+        cases = cases,
+        names = names
+    );
+
+    std::iter::once(BundleFile {
+        path: "autocshell.bash".to_owned(),
+        contents: stubs,
+    })
+    .chain(cfgs.iter().map(|cfg| BundleFile {
+        path: format!("bash/{}.bash", cfg.function_name()),
+        contents: generate_bash(cfg),
+    }))
+    .collect()
+}
+
+/// Each program's script becomes an autoloaded function file. The first call defines the real
+/// completion function (and its helpers) and then runs it.
+fn zsh_bundle(cfgs: &[Config]) -> Vec<BundleFile> {
+    let function_names = cfgs
+        .iter()
+        .map(|cfg| cfg.function_name())
+        .collect::<Vec<_>>();
+
+    let compdefs = cfgs
+        .iter()
+        .map(|cfg| format!("{}\n", format_compdef(cfg)))
+        .collect::<String>();
+
+    let stubs = format!(
+        "\
+        fpath=(${{${{(%):-%x}}:A:h}}/zsh $fpath)\n\
+        autoload -Uz {function_names}\n\
+        {compdefs}",
+        function_names = function_names.join(" "),
+        compdefs = compdefs
+    );
+
+    std::iter::once(BundleFile {
+        path: "autocshell.zsh".to_owned(),
+        contents: stubs,
+    })
+    .chain(cfgs.iter().map(|cfg| BundleFile {
+        path: format!("zsh/{}", cfg.function_name()),
+        contents: format!("{}\n\n{} \"$@\"\n", generate_zsh(cfg), cfg.function_name()),
+    }))
+    .collect()
+}
+
+/// Returns the files of a bundle of the programs for the given shell: a stub file that only
+/// registers the programs and one script per program that is loaded on first completion.
+pub fn generate_bundle(cfgs: &[Config], shell: &str) -> Result<Vec<BundleFile>, String> {
+    check_function_names(cfgs)?;
+
+    match shell {
+        "bash" => Ok(bash_bundle(cfgs)),
+        "zsh" => Ok(zsh_bundle(cfgs)),
+        _ => Err(format!("Shell `{}` is not supported", shell)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(program_names: &str) -> Config {
+        Config::from_string(&format!(
+            "\
+            program_names: {}
+            option:
+                long: --verbose
+            ",
+            program_names
+        ))
+        .unwrap()
+    }
+
+    fn contents<'a>(files: &'a [BundleFile], path: &str) -> &'a str {
+        &files
+            .iter()
+            .find(|file| file.path == path)
+            .unwrap()
+            .contents
+    }
+
+    #[test]
+    fn bundles_register_every_program() {
+        let cfgs = [config("[ourtool, ourtool-dev]"), config("[my.tool]")];
+
+        let files = generate_bundle(&cfgs, "bash").unwrap();
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "autocshell.bash",
+                "bash/_ourtool.bash",
+                "bash/_my_2e_tool.bash"
+            ]
+        );
+        let stubs = contents(&files, "autocshell.bash");
+        assert!(stubs.contains("    ourtool|ourtool-dev) file=_ourtool.bash ;;\n"));
+        assert!(stubs.contains("    my.tool) file=_my_2e_tool.bash ;;\n"));
+        assert!(stubs.ends_with(
+            "complete -F _autocshell_bundle_load -o bashdefault -o default ourtool ourtool-dev my.tool\n"
+        ));
+        assert_eq!(
+            contents(&files, "bash/_ourtool.bash"),
+            generate_bash(&cfgs[0])
+        );
+
+        let files = generate_bundle(&cfgs, "zsh").unwrap();
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["autocshell.zsh", "zsh/_ourtool", "zsh/_my_2e_tool"]);
+        assert_eq!(
+            contents(&files, "autocshell.zsh"),
+            "fpath=(${${(%):-%x}:A:h}/zsh $fpath)\n\
+            autoload -Uz _ourtool _my_2e_tool\n\
+            compdef _ourtool ourtool ourtool-dev\n\
+            compdef _my_2e_tool my.tool\n"
+        );
+        assert!(contents(&files, "zsh/_my_2e_tool").ends_with("\n\n_my_2e_tool \"$@\"\n"));
+
+        assert_eq!(
+            generate_bundle(&cfgs, "fish").err().unwrap(),
+            "Shell `fish` is not supported"
+        );
+    }
+
+    #[test]
+    fn programs_must_not_share_a_function() {
+        let cfgs = [
+            config("[ourtool]"),
+            config("[othertool]"),
+            config("[ourtool, ot]"),
+        ];
+
+        assert_eq!(
+            generate_bundle(&cfgs, "bash").err().unwrap(),
+            "Programs 'ourtool' and 'ourtool' would share the completion function '_ourtool'"
+        );
+    }
+}
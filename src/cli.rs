@@ -1,10 +1,32 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::path::Path;
 
 pub struct Cli {
     pub shell: Option<String>,
     pub cfg_file: String,
     pub output_file: Option<String>,
     pub show_cfg_help: bool,
+    pub lint: bool,
+    pub runtime: bool,
+    pub bundle: Option<Bundle>,
+    pub complete: Option<Complete>,
+    pub compile: Option<Compile>,
+}
+
+pub struct Complete {
+    pub spec_file: String,
+    pub words: Vec<String>,
+}
+
+pub struct Compile {
+    pub cfg_file: String,
+    pub output_file: String,
+}
+
+pub struct Bundle {
+    pub cfg_files: Vec<String>,
+    pub output_dir: String,
+    pub shells: Vec<String>,
 }
 
 fn build_app<'a, 'b>() -> App<'a, 'b> {
@@ -12,6 +34,7 @@ fn build_app<'a, 'b>() -> App<'a, 'b> {
         .version("0.5.3")
         .author("George Liontos <georgeliontos98@gmail.com>")
         .about("Generate autocompletion shell scripts for you application!")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("cfg_file")
                 .short("c")
@@ -39,6 +62,12 @@ fn build_app<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(false)
                 .help("Show help/explanation about the configuration file")
         )
+        .arg(
+            Arg::with_name("lint")
+                .long("lint")
+                .takes_value(false)
+                .help("Report deprecated options and other problems of the configuration instead of generating a script")
+        )
         .arg(
             Arg::with_name("shell")
                 .short("s")
@@ -48,26 +77,204 @@ fn build_app<'a, 'b>() -> App<'a, 'b> {
                 .max_values(1)
                 .help("Specify the shell to generate the script for")
         )
+        .arg(
+            Arg::with_name("runtime")
+                .long("runtime")
+                .takes_value(false)
+                .help("Generate a script that asks `autocshell complete` for the candidates instead of working them out itself")
+        )
+        .subcommand(
+            SubCommand::with_name("complete")
+                .about("Print the candidates for the last of the given words, one per line followed by a tab and its description")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("spec_file")
+                        .long("spec")
+                        .value_name("CONFIG_FILE")
+                        .help("The configuration file of the program, or its compiled specification")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("words")
+                        .value_name("WORDS")
+                        .help("The words of the line up to the cursor, starting with the program name")
+                        .multiple(true)
+                        .last(true)
+                        .allow_hyphen_values(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Compile a configuration file into a binary specification that `autocshell complete` loads faster")
+                .arg(
+                    Arg::with_name("cfg_file")
+                        .value_name("CONFIG_FILE")
+                        .help("The configuration file to compile")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT_FILE")
+                        .help("Specify the name of the compiled file. It defaults to the configuration filename with the .acsc extension")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("bundle")
+                .about("Generate one file per shell that loads the completion of each program on first use")
+                .arg(
+                    Arg::with_name("cfg_files")
+                        .value_name("CONFIG_FILE")
+                        .help("The configuration files of the programs to bundle")
+                        .multiple(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("output_dir")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT_DIR")
+                        .help("Specify the directory to write the bundle to")
+                        .takes_value(true)
+                        .default_value(".")
+                )
+                .arg(
+                    Arg::with_name("shell")
+                        .short("s")
+                        .long("shell")
+                        .takes_value(true)
+                        .possible_values(&["bash", "zsh"])
+                        .help("Specify the shell to bundle for. All supported shells are bundled if it is omitted")
+                )
+        )
+}
+
+impl Complete {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let spec_file = matches.value_of("spec_file").unwrap_or_default().to_owned();
+        let words = matches
+            .values_of("words")
+            .map(|words| words.map(|w| w.to_owned()).collect())
+            .unwrap_or_default();
+
+        Self { spec_file, words }
+    }
+}
+
+impl Compile {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let cfg_file = matches.value_of("cfg_file").unwrap_or_default().to_owned();
+        let output_file = match matches.value_of("output_file") {
+            Some(output_file) => output_file.to_owned(),
+            None => Path::new(&cfg_file)
+                .with_extension("acsc")
+                .to_string_lossy()
+                .into_owned(),
+        };
+
+        Self {
+            cfg_file,
+            output_file,
+        }
+    }
+}
+
+impl Bundle {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let cfg_files = matches
+            .values_of("cfg_files")
+            .map(|files| files.map(|f| f.to_owned()).collect())
+            .unwrap_or_default();
+        let output_dir = matches.value_of("output_dir").unwrap_or(".").to_owned();
+        let shells = match matches.value_of("shell") {
+            Some(shell) => vec![shell.to_owned()],
+            None => vec!["bash".to_owned(), "zsh".to_owned()],
+        };
+
+        Self {
+            cfg_files,
+            output_dir,
+            shells,
+        }
+    }
 }
 
 impl Cli {
     pub fn from_args() -> Self {
-        let app = build_app();
-        let matches = app.get_matches();
+        Self::from_matches(&build_app().get_matches())
+    }
 
+    fn from_matches(matches: &ArgMatches) -> Self {
         let show_cfg_help = matches.is_present("cfg_help");
+        let lint = matches.is_present("lint");
         let shell = matches.value_of("shell").map(|s| s.to_owned());
         let output_file = matches.value_of("output_file").map(|o| o.to_owned());
-        let cfg_file = matches
-            .value_of("cfg_file")
-            .expect("Please provide a configuratio file")
-            .to_owned();
+        let runtime = matches.is_present("runtime");
+        let bundle = matches.subcommand_matches("bundle").map(Bundle::from_matches);
+        let complete = matches
+            .subcommand_matches("complete")
+            .map(Complete::from_matches);
+        let compile = matches.subcommand_matches("compile").map(Compile::from_matches);
+        let cfg_file = match (&bundle, &complete, &compile) {
+            (None, None, None) => matches
+                .value_of("cfg_file")
+                .expect("Please provide a configuratio file")
+                .to_owned(),
+            _ => String::new(),
+        };
 
         Self {
             shell,
             cfg_file,
             output_file,
             show_cfg_help,
+            lint,
+            runtime,
+            bundle,
+            complete,
+            compile,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_complete(args: &[&str]) -> Complete {
+        let matches = build_app()
+            .get_matches_from_safe(args)
+            .unwrap_or_else(|e| panic!("{}", e));
+        Cli::from_matches(&matches).complete.unwrap()
+    }
+
+    #[test]
+    fn complete_passes_the_words_through() {
+        let complete = parse_complete(&[
+            "autocshell",
+            "complete",
+            "--spec",
+            "prog.cfg",
+            "--",
+            "prog",
+            "--color=a",
+            "-c",
+            "",
+        ]);
+        assert_eq!(complete.spec_file, "prog.cfg");
+        assert_eq!(complete.words, ["prog", "--color=a", "-c", ""]);
+
+        let complete = parse_complete(&["autocshell", "complete", "--spec", "prog.cfg"]);
+        assert!(complete.words.is_empty());
+    }
+
+    #[test]
+    fn complete_requires_a_spec() {
+        assert!(build_app()
+            .get_matches_from_safe(["autocshell", "complete", "--", "prog", ""])
+            .is_err());
+    }
+}
@@ -0,0 +1,779 @@
+//! Compiled specifications: a validated `Config` stored in a compact binary form that
+//! `autocshell complete` loads without parsing the configuration again.
+//!
+//! A compiled file starts with a header whose layout never changes between versions:
+//!
+//! ```text
+//! magic "ACSC" | format version (u32) | hash of the source (u64) | path of the source (string)
+//! ```
+//!
+//! followed by the configuration, encoded as described by the version. Integers are little
+//! endian, strings and lists are prefixed with their length (LEB128) and enums with a tag (u8).
+//! The hash covers the source and the files it reads fixed values from, so a compiled file
+//! whose source or any of those files has changed since it was compiled, or that was compiled
+//! by another version, is compiled again from its source when it is loaded.
+
+use crate::config::*;
+use std::{fs, path::Path};
+
+const MAGIC: &[u8] = b"ACSC";
+const FORMAT_VERSION: u32 = 1;
+
+/// Tags of the value kinds, in the order they are encoded in.
+const VALUE_KINDS: [ValueKind; 12] = [
+    ValueKind::Command,
+    ValueKind::User,
+    ValueKind::Group,
+    ValueKind::Host,
+    ValueKind::Port,
+    ValueKind::Pid,
+    ValueKind::Signal,
+    ValueKind::EnvVar,
+    ValueKind::Interface,
+    ValueKind::Locale,
+    ValueKind::Timezone,
+    ValueKind::CommandLine,
+];
+
+/// FNV-1a, which is plenty for noticing that a configuration file has been edited.
+fn hash(bytes: &[u8]) -> u64 {
+    hash_more(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+fn hash_more(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hashes the source along with the version of autocshell and the contents of the files it
+/// reads values from. A file that can't be read hashes like an empty one, and parsing the source
+/// reports it.
+fn source_hash(source_file: &str, source: &[u8]) -> u64 {
+    let hash = hash_more(hash(source), env!("CARGO_PKG_VERSION").as_bytes());
+    Config::referenced_files(&String::from_utf8_lossy(source), source_file)
+        .iter()
+        .fold(hash, |hash, file| {
+            let contents = fs::read(file).unwrap_or_default();
+            // The length keeps the contents of one file from passing for those of another.
+            let hash = hash_more(hash, &(contents.len() as u64).to_le_bytes());
+            hash_more(hash, &contents)
+        })
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Lengths are LEB128 encoded, so the many empty strings of a configuration take a byte.
+    fn len(&mut self, mut len: usize) {
+        while len >= 0x80 {
+            self.u8(len as u8 | 0x80);
+            len >>= 7;
+        }
+        self.u8(len as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.len(values.len());
+        for value in values {
+            self.str(value);
+        }
+    }
+
+    fn opt_u64(&mut self, value: Option<u64>) {
+        match value {
+            Some(value) => {
+                self.bool(true);
+                self.u64(value);
+            }
+            None => self.bool(false),
+        }
+    }
+
+    fn file_filter(&mut self, filter: &FileFilter) {
+        match &filter.kind {
+            FileKind::All => self.u8(0),
+            FileKind::Glob(glob) => {
+                self.u8(1);
+                self.str(glob);
+            }
+            FileKind::Directories => self.u8(2),
+            FileKind::Executables => self.u8(3),
+        }
+        self.bool(filter.include_hidden);
+    }
+
+    fn value_kind(&mut self, kind: ValueKind) {
+        let tag = VALUE_KINDS
+            .iter()
+            .position(|k| *k == kind)
+            .unwrap_or_default();
+        self.u8(tag as u8);
+    }
+
+    fn spec(&mut self, spec: &ValueSpec) {
+        match spec {
+            ValueSpec::Any => self.u8(0),
+            ValueSpec::Files(filter) => {
+                self.u8(1);
+                self.file_filter(filter);
+            }
+            ValueSpec::Fixed(values) => {
+                self.u8(2);
+                self.strings(values);
+            }
+            ValueSpec::Kind(kind) => {
+                self.u8(3);
+                self.value_kind(*kind);
+            }
+            ValueSpec::Command(command) => {
+                self.u8(4);
+                self.str(&command.command);
+                self.opt_u64(command.cache_ttl);
+            }
+            ValueSpec::Named(name) => {
+                self.u8(5);
+                self.str(name);
+            }
+        }
+    }
+
+    fn option_args(&mut self, args: &[OptionArg]) {
+        self.len(args.len());
+        for arg in args {
+            self.str(&arg.name);
+            self.spec(&arg.spec);
+        }
+    }
+
+    fn option(&mut self, o: &ProgramOption) {
+        self.str(&o.short);
+        self.str(&o.long);
+        self.str(&o.description);
+        self.strings(&o.fixed_values);
+        self.bool(o.accepts_files);
+        self.file_filter(&o.file_filter);
+        match o.value_kind {
+            Some(kind) => {
+                self.bool(true);
+                self.value_kind(kind);
+            }
+            None => self.bool(false),
+        }
+        self.str(&o.value_type_name);
+        self.str(&o.values_command);
+        self.opt_u64(o.cache_ttl);
+        self.str(&o.terminator);
+        self.bool(o.accepts_multiple);
+        self.bool(o.accepts_value);
+        match o.arity {
+            Some(arity) => {
+                self.bool(true);
+                self.len(arity.min);
+                self.len(arity.max);
+            }
+            None => self.bool(false),
+        }
+        self.option_args(&o.args);
+        self.str(&o.value_separator);
+        self.option_args(&o.suboptions);
+        self.bool(o.negatable);
+        self.strings(&o.aliases);
+        self.bool(o.hide_aliases);
+        self.u8(match o.attached_value {
+            AttachedValue::No => 0,
+            AttachedValue::Allowed => 1,
+            AttachedValue::Required => 2,
+        });
+        self.bool(o.hidden);
+        match &o.deprecated {
+            Some(message) => {
+                self.bool(true);
+                self.str(message);
+            }
+            None => self.bool(false),
+        }
+        self.str(&o.category);
+        self.str(&o.value_name);
+        self.str(&o.default_value);
+        self.str(&o.hint);
+        self.str(&o.env);
+    }
+
+    fn config(&mut self, cfg: &Config) {
+        self.str(&cfg.shell);
+        self.str(&cfg.program_name);
+        self.strings(&cfg.program_names);
+        self.bool(cfg.complete_paths);
+        self.bool(cfg.complete_env_names);
+        self.str(&cfg.function_prefix);
+        self.len(cfg.program_options.len());
+        for o in &cfg.program_options {
+            self.option(o);
+        }
+        self.bool(cfg.use_equals_sign);
+        self.str(&cfg.external_subcommands);
+        self.strings(&cfg.command_line_after);
+        self.len(cfg.value_types.len());
+        for value_type in &cfg.value_types {
+            self.str(&value_type.name);
+            self.spec(&value_type.spec);
+        }
+        self.len(cfg.env_vars.len());
+        for env_var in &cfg.env_vars {
+            self.str(&env_var.name);
+            self.str(&env_var.description);
+            self.spec(&env_var.spec);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("Compiled specification is truncated".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(invalid_tag(byte)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let mut len = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            len |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(len as usize);
+            }
+        }
+
+        Err("Compiled specification contains an invalid length".to_string())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "Compiled specification contains invalid UTF-8".to_string())
+    }
+
+    fn list<T>(&mut self, item: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let len = self.len()?;
+        // Every item takes at least one byte, which keeps a corrupted length from allocating.
+        let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+
+        Ok(items)
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, String> {
+        self.list(Self::string)
+    }
+
+    fn opt<T>(
+        &mut self,
+        item: impl Fn(&mut Self) -> Result<T, String>,
+    ) -> Result<Option<T>, String> {
+        if self.bool()? {
+            Ok(Some(item(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn file_filter(&mut self) -> Result<FileFilter, String> {
+        let kind = match self.u8()? {
+            0 => FileKind::All,
+            1 => FileKind::Glob(self.string()?),
+            2 => FileKind::Directories,
+            3 => FileKind::Executables,
+            tag => return Err(invalid_tag(tag)),
+        };
+
+        Ok(FileFilter {
+            kind,
+            include_hidden: self.bool()?,
+        })
+    }
+
+    fn value_kind(&mut self) -> Result<ValueKind, String> {
+        let tag = self.u8()?;
+        VALUE_KINDS
+            .get(tag as usize)
+            .copied()
+            .ok_or_else(|| invalid_tag(tag))
+    }
+
+    fn spec(&mut self) -> Result<ValueSpec, String> {
+        let spec = match self.u8()? {
+            0 => ValueSpec::Any,
+            1 => ValueSpec::Files(self.file_filter()?),
+            2 => ValueSpec::Fixed(self.strings()?),
+            3 => ValueSpec::Kind(self.value_kind()?),
+            4 => ValueSpec::Command(ValuesCommand {
+                command: self.string()?,
+                cache_ttl: self.opt(Self::u64)?,
+            }),
+            5 => ValueSpec::Named(self.string()?),
+            tag => return Err(invalid_tag(tag)),
+        };
+
+        Ok(spec)
+    }
+
+    fn option_arg(&mut self) -> Result<OptionArg, String> {
+        Ok(OptionArg {
+            name: self.string()?,
+            spec: self.spec()?,
+        })
+    }
+
+    fn option(&mut self) -> Result<ProgramOption, String> {
+        Ok(ProgramOption {
+            short: self.string()?,
+            long: self.string()?,
+            description: self.string()?,
+            fixed_values: self.strings()?,
+            accepts_files: self.bool()?,
+            file_filter: self.file_filter()?,
+            value_kind: self.opt(Self::value_kind)?,
+            value_type_name: self.string()?,
+            values_command: self.string()?,
+            cache_ttl: self.opt(Self::u64)?,
+            terminator: self.string()?,
+            accepts_multiple: self.bool()?,
+            accepts_value: self.bool()?,
+            arity: self.opt(|d| {
+                Ok(Arity {
+                    min: d.len()?,
+                    max: d.len()?,
+                })
+            })?,
+            args: self.list(Self::option_arg)?,
+            value_separator: self.string()?,
+            suboptions: self.list(Self::option_arg)?,
+            negatable: self.bool()?,
+            aliases: self.strings()?,
+            hide_aliases: self.bool()?,
+            attached_value: match self.u8()? {
+                0 => AttachedValue::No,
+                1 => AttachedValue::Allowed,
+                2 => AttachedValue::Required,
+                tag => return Err(invalid_tag(tag)),
+            },
+            hidden: self.bool()?,
+            deprecated: self.opt(Self::string)?,
+            category: self.string()?,
+            value_name: self.string()?,
+            default_value: self.string()?,
+            hint: self.string()?,
+            env: self.string()?,
+        })
+    }
+
+    fn config(&mut self) -> Result<Config, String> {
+        Ok(Config {
+            shell: self.string()?,
+            program_name: self.string()?,
+            program_names: self.strings()?,
+            complete_paths: self.bool()?,
+            complete_env_names: self.bool()?,
+            function_prefix: self.string()?,
+            program_options: self.list(Self::option)?,
+            use_equals_sign: self.bool()?,
+            external_subcommands: self.string()?,
+            command_line_after: self.strings()?,
+            value_types: self.list(|d| {
+                Ok(NamedValueType {
+                    name: d.string()?,
+                    spec: d.spec()?,
+                })
+            })?,
+            env_vars: self.list(|d| {
+                Ok(EnvVar {
+                    name: d.string()?,
+                    description: d.string()?,
+                    spec: d.spec()?,
+                })
+            })?,
+        })
+    }
+}
+
+fn invalid_tag(tag: u8) -> String {
+    format!("Compiled specification contains the invalid tag {}", tag)
+}
+
+/// The fixed part of a compiled file.
+struct Header {
+    version: u32,
+    source_hash: u64,
+    source_file: String,
+}
+
+fn encode(cfg: &Config, source_file: &str, source: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder {
+        bytes: MAGIC.to_vec(),
+    };
+    encoder.u32(FORMAT_VERSION);
+    encoder.u64(source_hash(source_file, source));
+    encoder.str(source_file);
+    encoder.config(cfg);
+    encoder.bytes
+}
+
+fn decode_header<'a>(decoder: &mut Decoder<'a>) -> Result<Header, String> {
+    decoder.take(MAGIC.len())?;
+    Ok(Header {
+        version: decoder.u32()?,
+        source_hash: decoder.u64()?,
+        source_file: decoder.string()?,
+    })
+}
+
+fn decode_config(decoder: &mut Decoder) -> Result<Config, String> {
+    let cfg = decoder.config()?;
+    if !decoder.bytes.is_empty() {
+        return Err("Compiled specification has trailing data".to_string());
+    }
+
+    cfg.check()
+        .map_err(|e| format!("Compiled specification is invalid: {}", e))?;
+    Ok(cfg)
+}
+
+/// Compiles the configuration file into `output_file`. The compiled file refers to the
+/// configuration by its absolute path, so it keeps track of it wherever it is loaded from.
+pub fn compile(cfg_file: &str, output_file: &str) -> Result<(), String> {
+    let source_file = fs::canonicalize(cfg_file)
+        .map_err(|_| "Couldn't read configuration file.".to_string())?
+        .to_string_lossy()
+        .into_owned();
+    let source =
+        fs::read(&source_file).map_err(|_| "Couldn't read configuration file.".to_string())?;
+    let cfg = Config::from_file(&source_file, FixedValuesCommands::Run)?;
+
+    fs::write(output_file, encode(&cfg, &source_file, &source))
+        .map_err(|_| format!("Couldn't write compiled specification '{}'", output_file))
+}
+
+/// Loads a specification, which is either a configuration file or a compiled one. `commands`
+/// tells whether the fixed_values_from_command fields of a configuration file are run. A
+/// compiled file that is out of date with its configuration is compiled again, running them,
+/// or rejected if its configuration is gone and it can't be read by this version.
+pub fn load_spec(spec_file: &str, commands: FixedValuesCommands) -> Result<Config, String> {
+    let bytes = fs::read(spec_file).map_err(|_| "Couldn't read configuration file.".to_string())?;
+    if !bytes.starts_with(MAGIC) {
+        return Config::from_file(spec_file, commands);
+    }
+
+    let mut decoder = Decoder { bytes: &bytes };
+    let header = decode_header(&mut decoder)?;
+    let up_to_date = header.version == FORMAT_VERSION;
+
+    if Path::new(&header.source_file).exists() {
+        let source = fs::read(&header.source_file)
+            .map_err(|_| "Couldn't read configuration file.".to_string())?;
+        if !up_to_date || source_hash(&header.source_file, &source) != header.source_hash {
+            let cfg = Config::from_file(&header.source_file, FixedValuesCommands::Run)?;
+            // Refreshing the compiled file only saves work for the next load, so failing to
+            // write it (e.g in a read-only directory) is not an error.
+            let _ = fs::write(spec_file, encode(&cfg, &header.source_file, &source));
+            return Ok(cfg);
+        }
+    }
+
+    if !up_to_date {
+        return Err(format!(
+            "'{}' was compiled by another version of autocshell and its configuration file '{}' is missing",
+            spec_file, header.source_file
+        ));
+    }
+
+    decode_config(&mut decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::complete::format_completions;
+    use std::path::PathBuf;
+
+    const CFG: &str = "\
+        shell: bash
+        program_name: prog
+        program_names: [prog, prog-dev]
+        function_prefix: _acs_
+        section: Output
+        option:
+            short: -c
+            long: --color
+            description: Colour the output, it's nice
+            fixed_values: [auto, always, never]
+            aliases: [--colour]
+            deprecated: use --theme instead
+        option:
+            short: -o
+            long: --output
+            files: *.txt no_hidden
+            attached_value: true
+        option:
+            long: --point
+            arity: 2..3
+            arg: x [1, 2]
+            arg: y signal
+        option:
+            long: --mount
+            suboption: uid user
+            suboption: ro
+            value_separator: \",\"
+        option:
+            long: --format
+            value_type: formats
+            env: PROG_FORMAT
+            hint: one of the formats
+        value_type formats:
+            values_command: printf 'json\\nyaml\\n'
+            cache_ttl: 1h
+        env PROG_HOME:
+            description: Where prog keeps its files
+            value_type: dir
+    ";
+
+    // Creates an empty directory for the test `name`, unique to this run of the tests.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("autocshell_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn compiled_specs_load_like_their_source() {
+        let cfg = Config::from_string(CFG).unwrap();
+        let bytes = encode(&cfg, "/tmp/prog.cfg", CFG.as_bytes());
+
+        let mut decoder = Decoder { bytes: &bytes };
+        let header = decode_header(&mut decoder).unwrap();
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(
+            header.source_hash,
+            hash_more(hash(CFG.as_bytes()), env!("CARGO_PKG_VERSION").as_bytes())
+        );
+        assert_eq!(header.source_file, "/tmp/prog.cfg");
+
+        let decoded = decode_config(&mut decoder).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", cfg));
+
+        let mut decoder = Decoder {
+            bytes: &bytes[..bytes.len() - 1],
+        };
+        decode_header(&mut decoder).unwrap();
+        assert_eq!(
+            decode_config(&mut decoder).unwrap_err(),
+            "Compiled specification is truncated"
+        );
+    }
+
+    #[test]
+    fn compiled_specs_follow_their_source() {
+        let dir = test_dir("compiled");
+        let cfg_file = dir.join("prog.cfg");
+        let compiled_file = dir.join("prog.acsc");
+        let cfg_file = cfg_file.to_str().unwrap();
+        let compiled_file = compiled_file.to_str().unwrap();
+        let load = || load_spec(compiled_file, FixedValuesCommands::Skip).unwrap();
+
+        fs::write(cfg_file, "program_name: prog\noption:\n    long: --old\n").unwrap();
+        compile(cfg_file, compiled_file).unwrap();
+        assert_eq!(load().program_options[0].long, "--old");
+
+        fs::write(cfg_file, "program_name: prog\noption:\n    long: --new\n").unwrap();
+        assert_eq!(load().program_options[0].long, "--new");
+
+        // The compiled file has been refreshed and no longer needs its source.
+        fs::remove_file(cfg_file).unwrap();
+        assert_eq!(load().program_options[0].long, "--new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compiled_specs_follow_their_fixed_values_files() {
+        let dir = test_dir("compiled_values");
+        let cfg_file = dir.join("prog.cfg");
+        let compiled_file = dir.join("prog.acsc");
+        let cfg_file = cfg_file.to_str().unwrap();
+        let compiled_file = compiled_file.to_str().unwrap();
+        let load = || load_spec(compiled_file, FixedValuesCommands::Skip).unwrap();
+
+        fs::write(
+            cfg_file,
+            "program_name: prog\noption:\n    long: --target\n    fixed_values_file: targets.txt\n",
+        )
+        .unwrap();
+        fs::write(dir.join("targets.txt"), "x86_64\n").unwrap();
+        compile(cfg_file, compiled_file).unwrap();
+        assert_eq!(load().program_options[0].fixed_values, ["x86_64"]);
+
+        fs::write(dir.join("targets.txt"), "x86_64\naarch64\n").unwrap();
+        assert_eq!(
+            load().program_options[0].fixed_values,
+            ["x86_64", "aarch64"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fixed_values_commands_only_run_when_compiling() {
+        let dir = test_dir("compiled_commands");
+        let cfg_file = dir.join("prog.cfg");
+        let compiled_file = dir.join("prog.acsc");
+        let cfg_file = cfg_file.to_str().unwrap();
+        let compiled_file = compiled_file.to_str().unwrap();
+        let runs = || fs::read_to_string(dir.join("runs")).map_or(0, |runs| runs.len());
+
+        fs::write(
+            cfg_file,
+            "program_name: prog\noption:\n    long: --target\n    \
+             fixed_values_from_command: echo >> runs; echo x86_64\n",
+        )
+        .unwrap();
+        let cfg = load_spec(cfg_file, FixedValuesCommands::Skip).unwrap();
+        assert!(cfg.program_options[0].fixed_values.is_empty());
+        assert_eq!(runs(), 0);
+
+        compile(cfg_file, compiled_file).unwrap();
+        assert_eq!(runs(), 1);
+        let cfg = load_spec(compiled_file, FixedValuesCommands::Skip).unwrap();
+        assert_eq!(cfg.program_options[0].fixed_values, ["x86_64"]);
+        assert_eq!(runs(), 1);
+
+        let cfg = load_spec(cfg_file, FixedValuesCommands::Run).unwrap();
+        assert_eq!(cfg.program_options[0].fixed_values, ["x86_64"]);
+        assert_eq!(runs(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_compiled_specs_are_rejected() {
+        let decode = |cfg: &Config| {
+            let bytes = encode(cfg, "/tmp/prog.cfg", CFG.as_bytes());
+            let mut decoder = Decoder { bytes: &bytes };
+            decode_header(&mut decoder).unwrap();
+            decode_config(&mut decoder)
+        };
+
+        let mut cfg = Config::from_string(CFG).unwrap();
+        cfg.program_options[2].arity = Some(Arity { min: 3, max: 2 });
+        assert_eq!(
+            decode(&cfg).unwrap_err(),
+            "Compiled specification is invalid: \
+             Option has an arity with no values or a minimum above its maximum (--point)"
+        );
+
+        let mut cfg = Config::from_string(CFG).unwrap();
+        cfg.value_types.clear();
+        assert_eq!(
+            decode(&cfg).unwrap_err(),
+            "Compiled specification is invalid: Unknown value type 'formats'"
+        );
+
+        let mut cfg = Config::from_string(CFG).unwrap();
+        cfg.program_options[0].env = "A;B".to_owned();
+        assert_eq!(
+            decode(&cfg).unwrap_err(),
+            "Compiled specification is invalid: \
+             Invalid environment variable name in 'env' (-c, --color, --colour)"
+        );
+
+        let mut cfg = Config::from_string(CFG).unwrap();
+        cfg.program_names.push("a;b".to_owned());
+        assert_eq!(
+            decode(&cfg).unwrap_err(),
+            "Compiled specification is invalid: Invalid program name 'a;b'"
+        );
+    }
+
+    #[test]
+    fn configs_and_compiled_specs_complete_alike() {
+        let dir = test_dir("complete_spec");
+        let cfg_file = dir.join("prog.cfg");
+        let compiled_file = dir.join("prog.acsc");
+        let cfg_file = cfg_file.to_str().unwrap();
+        let compiled_file = compiled_file.to_str().unwrap();
+
+        fs::write(cfg_file, CFG).unwrap();
+        compile(cfg_file, compiled_file).unwrap();
+
+        for spec_file in &[cfg_file, compiled_file] {
+            let cfg = load_spec(spec_file, FixedValuesCommands::Skip).unwrap();
+            assert_eq!(
+                format_completions(&cfg, &["prog", "--for"]),
+                "--format\tenv: PROG_FORMAT\n",
+                "{}",
+                spec_file
+            );
+            assert_eq!(
+                format_completions(&cfg, &["prog", "-c", "a"]),
+                "auto\t\nalways\t\n",
+                "{}",
+                spec_file
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,764 @@
+//! Completion without a shell: works out the candidates for a command line from a `Config`
+//! with the same semantics the generated scripts implement. The tests check it against the
+//! generated bash script; the zsh script needs an interactive shell and isn't compared.
+
+use crate::config::*;
+use std::{env, fs, path::Path, process::Command};
+
+/// A value that completes the current word, with the description shown next to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub value: String,
+    pub description: String,
+}
+
+impl Candidate {
+    fn new(value: String) -> Self {
+        Self {
+            value,
+            description: String::new(),
+        }
+    }
+}
+
+/// Signal names as zsh offers them for signal values.
+const SIGNALS: &[&str] = &[
+    "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2",
+    "PIPE", "ALRM", "TERM", "STKFLT", "CHLD", "CONT", "STOP", "TSTP", "TTIN", "TTOU", "URG",
+    "XCPU", "XFSZ", "VTALRM", "PROF", "WINCH", "IO", "PWR", "SYS",
+];
+
+/// What a word before the cursor turned out to be.
+enum Word<'a> {
+    /// An option, with the value given in the same word (--name=value or -Ovalue).
+    Option(&'a ProgramOption, Option<&'a str>),
+    /// The --no-<long> form of a negatable option.
+    Negated(&'a ProgramOption),
+    Other,
+}
+
+#[inline]
+fn is_option_like(word: &str) -> bool {
+    word.starts_with('-') || word.starts_with('+')
+}
+
+fn classify<'a>(cfg: &'a Config, word: &'a str) -> Word<'a> {
+    if let Some(option) = cfg.option_named(word) {
+        return Word::Option(option, None);
+    }
+
+    if let Some(option) = cfg
+        .program_options
+        .iter()
+        .find(|o| o.negated().is_some_and(|negated| negated.long == word))
+    {
+        return Word::Negated(option);
+    }
+
+    if let Some((name, value)) = word.split_once('=') {
+        if let Some(option) = cfg.option_named(name).filter(|o| o.accepts_value) {
+            return Word::Option(option, Some(value));
+        }
+    }
+
+    // Longer names go first so that e.g -Xmx is not mistaken for -X with value mx.
+    cfg.program_options
+        .iter()
+        .filter(|o| {
+            o.attached_value != AttachedValue::No
+                && word.len() > o.short.len()
+                && word.starts_with(o.short.as_str())
+        })
+        .max_by_key(|o| o.short.len())
+        .map_or(Word::Other, |o| {
+            Word::Option(o, Some(&word[o.short.len()..]))
+        })
+}
+
+/// Returns how many of the words after the option are its values.
+fn value_count(cfg: &Config, option: &ProgramOption, following: &[&str]) -> usize {
+    if !option.accepts_value || option.attached_value == AttachedValue::Required {
+        return 0;
+    }
+
+    match option.arity {
+        None => 1.min(following.len()),
+        Some(arity) => following
+            .iter()
+            .take(arity.max)
+            .enumerate()
+            .take_while(|(position, word)| {
+                *position < arity.min || cfg.option_named(word).is_none()
+            })
+            .count(),
+    }
+}
+
+/// The value given on the line to the option with the given name, for use in values commands.
+fn option_value_on_line(cfg: &Config, words: &[&str], name: &str) -> String {
+    let names = cfg
+        .option_named(name)
+        .map(|option| option.names())
+        .unwrap_or_else(|| vec![name]);
+
+    let mut value = "";
+    for (i, word) in words.iter().enumerate() {
+        if names.contains(word) {
+            value = words.get(i + 1).copied().unwrap_or("");
+        } else if let Some((given, given_value)) = word.split_once('=') {
+            if names.contains(&given) {
+                value = given_value;
+            }
+        }
+    }
+
+    value.to_owned()
+}
+
+fn command_output(cfg: &Config, command: &ValuesCommand, words: &[&str]) -> Vec<String> {
+    let command = expand_option_references(&command.command, |name| {
+        format!(
+            "'{}'",
+            option_value_on_line(cfg, words, name).replace('\'', "'\\''")
+        )
+    });
+
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Matches `*`, `?` and `[...]` patterns like the shells do for file globs.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    fn matches(glob: &[char], name: &[char]) -> bool {
+        match glob.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|skip| matches(&glob[1..], &name[skip..])),
+            Some('?') => !name.is_empty() && matches(&glob[1..], &name[1..]),
+            Some('[') => match (glob.iter().position(|&c| c == ']'), name.first()) {
+                (Some(end), Some(c)) if end > 1 => {
+                    let class = &glob[1..end];
+                    let (negate, class) = match class.first() {
+                        Some('!') | Some('^') => (true, &class[1..]),
+                        _ => (false, class),
+                    };
+                    let mut found = false;
+                    let mut i = 0;
+                    while i < class.len() {
+                        if i + 2 < class.len() && class[i + 1] == '-' {
+                            found |= class[i] <= *c && *c <= class[i + 2];
+                            i += 3;
+                        } else {
+                            found |= class[i] == *c;
+                            i += 1;
+                        }
+                    }
+
+                    found != negate && matches(&glob[(end + 1)..], &name[1..])
+                }
+                _ => false,
+            },
+            Some(c) => name.first() == Some(c) && matches(&glob[1..], &name[1..]),
+        }
+    }
+
+    let glob = glob.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches(&glob, &name)
+}
+
+/// Completes the path in `word` with the entries of its directory, relative to `base`.
+/// Directories end with a slash so that they can be completed further.
+fn path_values(base: &Path, word: &str, filter: &FileFilter) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => (&word[..(slash + 1)], &word[(slash + 1)..]),
+        None => ("", word),
+    };
+
+    let entries = match fs::read_dir(base.join(if dir.is_empty() { "." } else { dir })) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut values = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix)
+                || (name.starts_with('.') && !(filter.include_hidden && prefix.starts_with('.')))
+            {
+                return None;
+            }
+
+            let metadata = fs::metadata(entry.path()).ok()?;
+            if metadata.is_dir() {
+                return Some(format!("{}{}/", dir, name));
+            }
+
+            let wanted = match &filter.kind {
+                FileKind::All => true,
+                FileKind::Directories => false,
+                FileKind::Glob(glob) => glob_matches(glob, &name),
+                FileKind::Executables => is_executable(&metadata),
+            };
+
+            if wanted {
+                Some(format!("{}{}", dir, name))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    values.sort();
+    values
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+/// The first field of each line of a file like /etc/passwd, skipping comments.
+fn first_fields(file: &str, separator: char) -> Vec<String> {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split(separator).next())
+        .map(|field| field.trim().to_owned())
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+fn command_names() -> Vec<String> {
+    let mut names = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| fs::metadata(entry.path()).is_ok_and(|m| m.is_file() && is_executable(&m)))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn dir_entries(dir: &str) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn kind_values(kind: ValueKind, word: &str) -> Vec<String> {
+    match kind {
+        ValueKind::Command | ValueKind::CommandLine => command_names(),
+        ValueKind::User => first_fields("/etc/passwd", ':'),
+        ValueKind::Group => first_fields("/etc/group", ':'),
+        ValueKind::Host => fs::read_to_string("/etc/hosts")
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace().skip(1).map(|host| host.to_owned()))
+            .collect(),
+        ValueKind::Port => first_fields("/etc/services", '\t')
+            .into_iter()
+            .filter_map(|field| field.split_whitespace().next().map(|s| s.to_owned()))
+            .collect(),
+        ValueKind::Pid => dir_entries("/proc")
+            .into_iter()
+            .filter(|entry| entry.chars().all(|c| c.is_ascii_digit()))
+            .collect(),
+        ValueKind::Signal => SIGNALS.iter().map(|signal| signal.to_string()).collect(),
+        ValueKind::EnvVar => env::vars_os()
+            .map(|(name, _)| name.to_string_lossy().into_owned())
+            .collect(),
+        ValueKind::Interface => dir_entries("/sys/class/net"),
+        ValueKind::Locale => Command::new("locale")
+            .arg("-a")
+            .output()
+            .map(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(|line| line.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ValueKind::Timezone => path_values(
+            Path::new("/usr/share/zoneinfo"),
+            word,
+            &FileFilter::default(),
+        ),
+    }
+}
+
+/// Returns the values of `spec` that complete `word`.
+fn spec_values(cfg: &Config, spec: &ValueSpec, word: &str, words: &[&str]) -> Vec<String> {
+    let values = match spec {
+        ValueSpec::Any => Vec::new(),
+        ValueSpec::Files(filter) => path_values(Path::new(""), word, filter),
+        // Fixed values are stored escaped for the generated scripts.
+        ValueSpec::Fixed(values) => values.iter().map(|v| v.replace("\\'", "'")).collect(),
+        ValueSpec::Kind(kind) => kind_values(*kind, word),
+        ValueSpec::Command(command) => command_output(cfg, command, words),
+        ValueSpec::Named(name) => {
+            return match cfg.value_type(name) {
+                Some(value_type) => spec_values(cfg, &value_type.spec, word, words),
+                None => Vec::new(),
+            }
+        }
+    };
+
+    values
+        .into_iter()
+        .filter(|value| value.starts_with(word))
+        .collect()
+}
+
+/// Completes an element of a list of values (e.g --features a,b,c) or of key=value pairs,
+/// leaving out the elements that are already in the list.
+fn value_list_candidates(
+    cfg: &Config,
+    option: &ProgramOption,
+    word: &str,
+    words: &[&str],
+) -> Vec<String> {
+    let separator = if option.value_separator.is_empty() {
+        ","
+    } else {
+        option.value_separator.as_str()
+    };
+
+    let (done, element) = match word.rfind(separator) {
+        Some(end) => (
+            &word[..(end + separator.len())],
+            &word[(end + separator.len())..],
+        ),
+        None => ("", word),
+    };
+    let used = done
+        .split(separator)
+        .map(|used| used.split('=').next().unwrap_or(used))
+        .collect::<Vec<_>>();
+
+    let elements: Vec<String> = if option.suboptions.is_empty() {
+        spec_values(cfg, &option.value_spec(), element, words)
+            .into_iter()
+            .filter(|value| !used.contains(&value.as_str()))
+            .collect()
+    } else if let Some((key, value)) = element.split_once('=') {
+        option
+            .suboptions
+            .iter()
+            .filter(|suboption| suboption.name == key)
+            .flat_map(|suboption| spec_values(cfg, &suboption.spec, value, words))
+            .map(|value| format!("{}={}", key, value))
+            .collect()
+    } else {
+        option
+            .suboptions
+            .iter()
+            .filter(|suboption| {
+                suboption.name.starts_with(element) && !used.contains(&suboption.name.as_str())
+            })
+            .map(|suboption| match suboption.spec {
+                ValueSpec::Any => suboption.name.clone(),
+                _ => format!("{}=", suboption.name),
+            })
+            .collect()
+    };
+
+    elements
+        .into_iter()
+        .map(|element| format!("{}{}", done, element))
+        .collect()
+}
+
+/// Completes the value at the given position after the option.
+fn value_candidates(
+    cfg: &Config,
+    option: &ProgramOption,
+    position: usize,
+    word: &str,
+    words: &[&str],
+) -> Vec<Candidate> {
+    let values = if option.suboptions.is_empty() && !option.takes_value_list() {
+        spec_values(cfg, &option.arg_at(position).spec, word, words)
+    } else {
+        value_list_candidates(cfg, option, word, words)
+    };
+
+    values.into_iter().map(Candidate::new).collect()
+}
+
+/// Lists the options that can still be given, in the order of their categories.
+fn option_candidates(cfg: &Config, used: &[&ProgramOption], word: &str) -> Vec<Candidate> {
+    let categories = cfg.categories();
+    let mut options = cfg
+        .program_options
+        .iter()
+        .filter(|o| !o.hidden && (o.accepts_multiple || !used.iter().any(|u| std::ptr::eq(*u, *o))))
+        .collect::<Vec<_>>();
+    options.sort_by_key(|o| {
+        categories
+            .iter()
+            .position(|category| *category == o.category)
+            .map_or(0, |position| position + 1)
+    });
+
+    options
+        .into_iter()
+        .flat_map(|o| {
+            // Descriptions are kept with their quotes escaped for the generated scripts.
+            let description = o.listed_description().replace("\\'", "'");
+            let names = o.visible_names().into_iter().map(move |name| Candidate {
+                value: name.to_owned(),
+                description: description.clone(),
+            });
+
+            let negated = o.negated().map(|negated| Candidate {
+                value: negated.long,
+                description: negated.description,
+            });
+
+            names.chain(negated)
+        })
+        .filter(|candidate| candidate.value.starts_with(word))
+        .collect()
+}
+
+/// The executables in PATH that act as subcommands of the program, without their prefix.
+fn external_subcommand_candidates(cfg: &Config, word: &str) -> Vec<Candidate> {
+    let prefix = format!("{}{}", cfg.external_subcommands, word);
+    command_names()
+        .into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .map(|name| Candidate::new(name[cfg.external_subcommands.len()..].to_owned()))
+        .collect()
+}
+
+/// Returns the candidates for the word at `current` (which may be one past the last word),
+/// given the words of the line with the program name first.
+pub fn complete(cfg: &Config, words: &[&str], current: usize) -> Vec<Candidate> {
+    let word = words.get(current).copied().unwrap_or("");
+    let before = &words[..current.min(words.len())];
+
+    let mut used = Vec::new();
+    let mut arguments = 0;
+    let mut i = 1;
+    while i < before.len() {
+        match classify(cfg, before[i]) {
+            Word::Option(option, inline_value) => {
+                used.push(option);
+                if option.delegates_command_line() && inline_value.is_none() {
+                    let rest = &before[(i + 1)..];
+                    match rest
+                        .iter()
+                        .position(|w| !option.terminator.is_empty() && *w == option.terminator)
+                    {
+                        Some(end) => i += end + 2,
+                        None if rest.is_empty() => {
+                            return value_candidates(cfg, option, 0, word, words);
+                        }
+                        // The rest of the line is completed by the command's own completion.
+                        None => return Vec::new(),
+                    }
+                    continue;
+                }
+
+                if inline_value.is_some() {
+                    i += 1;
+                    continue;
+                }
+
+                let count = value_count(cfg, option, &before[(i + 1)..]);
+                i += count + 1;
+                let max = option.arity.map_or(1, |arity| arity.max);
+                if i == before.len()
+                    && count < max
+                    && option.accepts_value
+                    && option.attached_value != AttachedValue::Required
+                    && !(option.arity.is_some_and(|arity| count >= arity.min)
+                        && is_option_like(word))
+                {
+                    return value_candidates(cfg, option, count, word, words);
+                }
+            }
+            Word::Negated(option) => {
+                used.push(option);
+                i += 1;
+            }
+            Word::Other if cfg.command_line_after.iter().any(|w| w == before[i]) => {
+                if i + 1 < before.len() {
+                    // The rest of the line is completed by the command's own completion.
+                    return Vec::new();
+                }
+
+                return command_names()
+                    .into_iter()
+                    .filter(|name| name.starts_with(word))
+                    .map(Candidate::new)
+                    .collect();
+            }
+            Word::Other => {
+                if !is_option_like(before[i]) {
+                    arguments += 1;
+                    // Everything after an external subcommand is completed by its own completion.
+                    if arguments == 1 && !cfg.external_subcommands.is_empty() {
+                        return Vec::new();
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+
+    // A short name on its own is completed with the values that can be glued to it.
+    let glued = cfg.program_options.iter().find(|o| {
+        o.attached_value != AttachedValue::No && o.short == word && o.value_spec() != ValueSpec::Any
+    });
+    let word_value = match glued {
+        Some(option) => Word::Option(option, Some("")),
+        None => classify(cfg, word),
+    };
+
+    if let Word::Option(option, Some(value)) = word_value {
+        let prefix = &word[..(word.len() - value.len())];
+        return value_candidates(cfg, option, 0, value, words)
+            .into_iter()
+            .map(|candidate| Candidate {
+                value: format!("{}{}", prefix, candidate.value),
+                ..candidate
+            })
+            .collect();
+    }
+
+    let mut candidates = option_candidates(cfg, &used, word);
+    if arguments == 0 && !cfg.external_subcommands.is_empty() && !is_option_like(word) {
+        candidates.extend(external_subcommand_candidates(cfg, word));
+    }
+
+    candidates
+}
+
+/// Formats the candidates for the last of the words the way `autocshell complete` prints them:
+/// one per line, followed by a tab and its description.
+pub fn format_completions(cfg: &Config, words: &[&str]) -> String {
+    if words.is_empty() {
+        return String::new();
+    }
+
+    complete(cfg, words, words.len() - 1)
+        .into_iter()
+        .map(|candidate| format!("{}\t{}\n", candidate.value, candidate.description))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(cfg: &Config, line: &str) -> Vec<String> {
+        let mut words = line.split(' ').collect::<Vec<_>>();
+        if words.last() == Some(&"") {
+            words.pop();
+        }
+        let current = if line.ends_with(' ') {
+            words.len()
+        } else {
+            words.len() - 1
+        };
+
+        complete(cfg, &words, current)
+            .into_iter()
+            .map(|candidate| candidate.value)
+            .collect()
+    }
+
+    const CFG: &str = "\
+        shell: bash
+        program_name: prog
+        option:
+            short: -c
+            long: --color
+            description: Colour the output
+            fixed_values: [auto, always, never, it's]
+        option:
+            long: --point
+            arg: x [1, 2]
+            arg: y [3, 4]
+        option:
+            long: --features
+            fixed_values: [foo, bar, baz]
+            value_separator: ,
+        option:
+            short: -O
+            fixed_values: [0, 1, 2]
+            attached_value: only
+        option:
+            long: --cache
+            negatable: true
+            accepts_value: false
+        option:
+            long: --verbose
+            accepts_value: false
+            accepts_multiple: true
+    ";
+
+    #[test]
+    fn options_are_listed_until_used() {
+        let cfg = Config::from_string(CFG).unwrap();
+
+        let candidates = complete(&cfg, &["prog", "--c"], 1);
+        assert_eq!(
+            candidates,
+            vec![
+                Candidate {
+                    value: "--color".to_owned(),
+                    description: "Colour the output".to_owned(),
+                },
+                Candidate {
+                    value: "--cache".to_owned(),
+                    description: String::new(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            values(&cfg, "prog --no-cache --"),
+            vec!["--color", "--point", "--features", "--verbose"]
+        );
+        assert_eq!(values(&cfg, "prog --verbose --v"), vec!["--verbose"]);
+    }
+
+    #[test]
+    fn values_are_completed_after_their_option() {
+        let cfg = Config::from_string(CFG).unwrap();
+
+        assert_eq!(values(&cfg, "prog --color a"), vec!["auto", "always"]);
+        assert_eq!(
+            values(&cfg, "prog -c auto --color=n"),
+            vec!["--color=never"]
+        );
+        assert_eq!(values(&cfg, "prog --point "), vec!["1", "2"]);
+        assert_eq!(values(&cfg, "prog --point 1 "), vec!["3", "4"]);
+        assert_eq!(
+            values(&cfg, "prog --features foo,"),
+            vec!["foo,bar", "foo,baz"]
+        );
+        assert_eq!(values(&cfg, "prog -O"), vec!["-O0", "-O1", "-O2"]);
+        assert_eq!(values(&cfg, "prog --point 1 3 --verb"), vec!["--verbose"]);
+    }
+
+    #[test]
+    fn descriptions_and_values_are_shown_unescaped() {
+        let cfg =
+            Config::from_string(&CFG.replace("Colour the output", "Colour the program's output"))
+                .unwrap();
+
+        let candidates = complete(&cfg, &["prog", "--col"], 1);
+        assert_eq!(candidates[0].description, "Colour the program's output");
+        assert_eq!(values(&cfg, "prog --color i"), vec!["it's"]);
+    }
+
+    // Runs the completion function of the generated bash script on the line, splitting the
+    // words on spaces as bash would for these lines, and returns the candidates without their
+    // descriptions.
+    fn bash_values(script: &str, line: &str) -> Vec<String> {
+        const DRIVER: &str = r#"
+            compopt() { :; }
+            COMP_LINE=$1 COMP_POINT=${#1}
+            read -ra COMP_WORDS <<< "${COMP_LINE}"
+            [[ ${COMP_LINE} == *' ' ]] && COMP_WORDS+=( '' )
+            COMP_CWORD=$(( ${#COMP_WORDS[@]} - 1 ))
+            _prog_completions prog
+            printf '%s\n' "${COMPREPLY[@]}"
+        "#;
+
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\n{}", script, DRIVER))
+            .arg("prog")
+            .arg(line)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "bash failed on '{}'", line);
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| entry.split(" -- ").next().unwrap().trim_end().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn generated_bash_script_offers_the_same_candidates() {
+        let cfg = Config::from_string(CFG).unwrap();
+        let script = crate::bash::generate_bash(&cfg);
+
+        for line in &[
+            "prog --c",
+            "prog --no-cache --",
+            "prog --verbose --v",
+            "prog --color a",
+            "prog --color i",
+            "prog -c ",
+            "prog --point ",
+            "prog --point 1 ",
+            "prog --features foo,",
+            "prog -O",
+            "prog --point 1 3 --verb",
+        ] {
+            assert_eq!(bash_values(&script, line), values(&cfg, line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn completions_are_printed_with_their_descriptions() {
+        let cfg = Config::from_string(CFG).unwrap();
+
+        assert_eq!(
+            format_completions(&cfg, &["prog", "--c"]),
+            "--color\tColour the output\n--cache\t\n"
+        );
+        assert_eq!(
+            format_completions(&cfg, &["prog", "--features", "foo,b"]),
+            "foo,bar\t\nfoo,baz\t\n"
+        );
+        assert_eq!(format_completions(&cfg, &["prog", "--nothing"]), "");
+        assert_eq!(format_completions(&cfg, &[]), "");
+    }
+
+    #[test]
+    fn globs_match_like_the_shells() {
+        assert!(glob_matches("*.toml", "Cargo.toml"));
+        assert!(!glob_matches("*.toml", "Cargo.lock"));
+        assert!(glob_matches("file?.[ch]", "file1.h"));
+        assert!(!glob_matches("file[!0-9].c", "file1.c"));
+    }
+}
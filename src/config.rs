@@ -2,9 +2,106 @@ use std::{
     default::Default,
     fs,
     iter::{Enumerate, Peekable},
+    num::IntErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
     str::Lines,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileKind {
+    All,
+    Glob(String),
+    Directories,
+    Executables,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileFilter {
+    pub kind: FileKind,
+    pub include_hidden: bool,
+}
+
+impl Default for FileFilter {
+    fn default() -> Self {
+        Self {
+            kind: FileKind::All,
+            include_hidden: true,
+        }
+    }
+}
+
+/// Built-in kinds of values that each shell already knows how to complete.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueKind {
+    Command,
+    User,
+    Group,
+    Host,
+    Port,
+    Pid,
+    Signal,
+    EnvVar,
+    Interface,
+    Locale,
+    Timezone,
+    /// A command followed by its arguments, completed by delegating to the command's completion.
+    CommandLine,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSpec {
+    Any,
+    Files(FileFilter),
+    Fixed(Vec<String>),
+    Kind(ValueKind),
+    /// A command whose output lines are the values.
+    Command(ValuesCommand),
+    /// A reference to a value type declared with a `value_type <name>:` block.
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValuesCommand {
+    pub command: String,
+    /// Seconds for which the output of the command is cached on disk.
+    pub cache_ttl: Option<u64>,
+}
+
+/// An environment variable that can be set in front of the command (e.g VAR=value prog).
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvVar {
+    pub name: String,
+    pub description: String,
+    pub spec: ValueSpec,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedValueType {
+    pub name: String,
+    pub spec: ValueSpec,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionArg {
+    pub name: String,
+    pub spec: ValueSpec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// Whether the value of a short option may be glued to it (e.g -ofile, -O2).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachedValue {
+    No,
+    Allowed,
+    Required,
+}
+
 #[derive(Debug, Clone)]
 pub struct ProgramOption {
     pub short: String,
@@ -12,8 +109,30 @@ pub struct ProgramOption {
     pub description: String,
     pub fixed_values: Vec<String>,
     pub accepts_files: bool,
+    pub file_filter: FileFilter,
+    pub value_kind: Option<ValueKind>,
+    pub value_type_name: String,
+    pub values_command: String,
+    pub cache_ttl: Option<u64>,
+    pub terminator: String,
     pub accepts_multiple: bool,
     pub accepts_value: bool,
+    pub arity: Option<Arity>,
+    pub args: Vec<OptionArg>,
+    pub value_separator: String,
+    pub suboptions: Vec<OptionArg>,
+    pub negatable: bool,
+    pub aliases: Vec<String>,
+    pub hide_aliases: bool,
+    pub attached_value: AttachedValue,
+    pub hidden: bool,
+    /// The deprecation message, empty if the option is deprecated without one.
+    pub deprecated: Option<String>,
+    pub category: String,
+    pub value_name: String,
+    pub default_value: String,
+    pub hint: String,
+    pub env: String,
 }
 
 impl Default for ProgramOption {
@@ -24,8 +143,29 @@ impl Default for ProgramOption {
             description: String::new(),
             fixed_values: Vec::new(),
             accepts_files: false,
+            file_filter: FileFilter::default(),
+            value_kind: None,
+            value_type_name: String::new(),
+            values_command: String::new(),
+            cache_ttl: None,
+            terminator: String::new(),
             accepts_multiple: false,
             accepts_value: true,
+            arity: None,
+            args: Vec::new(),
+            value_separator: String::new(),
+            suboptions: Vec::new(),
+            negatable: false,
+            aliases: Vec::new(),
+            hide_aliases: false,
+            attached_value: AttachedValue::No,
+            hidden: false,
+            deprecated: None,
+            category: String::new(),
+            value_name: String::new(),
+            default_value: String::new(),
+            hint: String::new(),
+            env: String::new(),
         }
     }
 }
@@ -33,21 +173,218 @@ impl Default for ProgramOption {
 impl ProgramOption {
     #[inline(always)]
     pub fn has_one_representation(&self) -> bool {
-        self.short.is_empty() ^ self.long.is_empty()
+        self.names().len() == 1
+    }
+
+    /// Returns every name the option is recognised by (short, long and aliases).
+    pub fn names(&self) -> Vec<&str> {
+        [&self.short, &self.long]
+            .iter()
+            .map(|name| name.as_str())
+            .chain(self.aliases.iter().map(|alias| alias.as_str()))
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// Returns the names that should be offered when listing options.
+    pub fn visible_names(&self) -> Vec<&str> {
+        if self.hide_aliases {
+            [&self.short, &self.long]
+                .iter()
+                .map(|name| name.as_str())
+                .filter(|name| !name.is_empty())
+                .collect()
+        } else {
+            self.names()
+        }
+    }
+
+    /// Returns the aliases that are recognised but not offered when listing options.
+    pub fn hidden_aliases(&self) -> &[String] {
+        if self.hide_aliases {
+            &self.aliases
+        } else {
+            &[]
+        }
+    }
+
+    /// Checks the rules that involve more than one field of the option.
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.negatable && self.long.is_empty() {
+            return Err("Negatable option must have a long name");
+        }
+
+        if self.attached_value != AttachedValue::No
+            && (self.short.is_empty() || !self.accepts_value)
+        {
+            return Err("Option with 'attached_value' must have a short name and accept a value");
+        }
+
+        if self.delegates_command_line() {
+            if self.arity.is_some() || self.takes_value_list() {
+                return Err(
+                    "Option with a command_line value cannot take multiple or listed values",
+                );
+            }
+        } else if !self.terminator.is_empty() {
+            return Err("'terminator' is only valid for options with a command_line value");
+        }
+
+        if self.describes_value() && !self.accepts_value {
+            return Err("Option with 'value_name', 'default' or 'hint' must accept a value");
+        }
+
+        if self.cache_ttl.is_some() && self.values_command.is_empty() {
+            return Err("'cache_ttl' requires a values_command");
+        }
+
+        if !self.env.is_empty() && !is_valid_env_var_name(&self.env) {
+            return Err("Invalid environment variable name in 'env'");
+        }
+
+        if let Some(arity) = self.arity {
+            // The parser never builds such an arity, but a compiled specification could hold one.
+            if arity.max == 0 || arity.min > arity.max {
+                return Err("Option has an arity with no values or a minimum above its maximum");
+            }
+
+            if !self.accepts_value {
+                return Err("Option with 'arity' must accept a value");
+            }
+
+            if self.args.len() > arity.max {
+                return Err("Option defines more 'arg' entries than its arity allows");
+            }
+        }
+
+        Ok(())
     }
 
     #[inline(always)]
     pub fn is_help(&self) -> bool {
         self.short == "-h" || self.long == "--help"
     }
+
+    pub fn value_spec(&self) -> ValueSpec {
+        if !self.value_type_name.is_empty() {
+            ValueSpec::Named(self.value_type_name.clone())
+        } else if let Some(kind) = self.value_kind {
+            ValueSpec::Kind(kind)
+        } else if !self.values_command.is_empty() {
+            ValueSpec::Command(ValuesCommand {
+                command: self.values_command.clone(),
+                cache_ttl: self.cache_ttl,
+            })
+        } else if self.accepts_files {
+            ValueSpec::Files(self.file_filter.clone())
+        } else if !self.fixed_values.is_empty() {
+            ValueSpec::Fixed(self.fixed_values.clone())
+        } else {
+            ValueSpec::Any
+        }
+    }
+
+    #[inline(always)]
+    pub fn delegates_command_line(&self) -> bool {
+        self.value_kind == Some(ValueKind::CommandLine)
+    }
+
+    #[inline(always)]
+    pub fn takes_value_list(&self) -> bool {
+        !self.value_separator.is_empty()
+    }
+
+    /// Returns the description to show when completing the option, noting if it's deprecated
+    /// and the environment variable that can be used instead of it.
+    pub fn listed_description(&self) -> String {
+        let mut notes = Vec::new();
+        match self.deprecated.as_deref() {
+            None => {}
+            Some("") => notes.push("deprecated".to_owned()),
+            Some(message) => notes.push(format!("deprecated: {}", message)),
+        }
+
+        if !self.env.is_empty() {
+            notes.push(format!("env: {}", self.env));
+        }
+
+        if notes.is_empty() {
+            self.description.clone()
+        } else if self.description.is_empty() {
+            notes.join("; ")
+        } else {
+            format!("{} ({})", self.description, notes.join("; "))
+        }
+    }
+
+    #[inline(always)]
+    pub fn describes_value(&self) -> bool {
+        !self.value_name.is_empty() || !self.default_value.is_empty() || !self.hint.is_empty()
+    }
+
+    /// Describes the value the option takes, e.g "PORT: TCP port (default: 8080)".
+    pub fn value_description(&self) -> String {
+        let mut description = self.value_name.clone();
+        if !self.hint.is_empty() {
+            if !description.is_empty() {
+                description.push_str(": ");
+            }
+            description.push_str(&self.hint);
+        }
+
+        if !self.default_value.is_empty() {
+            if !description.is_empty() {
+                description.push(' ');
+            }
+            description.push_str(&format!("(default: {})", self.default_value));
+        }
+
+        description
+    }
+
+    /// Returns the `--no-<long>` form of a negatable option, keeping the prefix of the long name.
+    pub fn negated(&self) -> Option<ProgramOption> {
+        if !self.negatable {
+            return None;
+        }
+
+        let name = self.long.trim_start_matches(['-', '+']);
+        let prefix = &self.long[..(self.long.len() - name.len())];
+
+        Some(ProgramOption {
+            long: format!("{}no-{}", prefix, name),
+            description: format!("Negate {}", self.long),
+            accepts_value: false,
+            ..ProgramOption::default()
+        })
+    }
+
+    /// Returns the argument expected at the given (zero based) position after the option.
+    /// Positions without an explicit `arg` entry fall back to the option's own value spec.
+    pub fn arg_at(&self, position: usize) -> OptionArg {
+        self.args.get(position).cloned().unwrap_or_else(|| OptionArg {
+            name: String::new(),
+            spec: self.value_spec(),
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub shell: String,
     pub program_name: String,
+    pub program_names: Vec<String>,
+    pub complete_paths: bool,
+    /// Whether the environment variables are offered for the initial word of every command.
+    pub complete_env_names: bool,
+    pub function_prefix: String,
     pub program_options: Vec<ProgramOption>,
     pub use_equals_sign: bool,
+    pub external_subcommands: String,
+    /// Words after which the rest of the line is a command with its arguments (e.g --).
+    pub command_line_after: Vec<String>,
+    pub value_types: Vec<NamedValueType>,
+    pub env_vars: Vec<EnvVar>,
 }
 
 impl Default for Config {
@@ -55,8 +392,16 @@ impl Default for Config {
         Self {
             shell: String::new(),
             program_name: String::new(),
+            program_names: Vec::new(),
+            complete_paths: false,
+            complete_env_names: false,
+            function_prefix: "_".to_owned(),
             program_options: Vec::new(),
             use_equals_sign: true,
+            external_subcommands: String::new(),
+            command_line_after: Vec::new(),
+            value_types: Vec::new(),
+            env_vars: Vec::new(),
         }
     }
 }
@@ -72,23 +417,201 @@ fn check_bool(value: &str) -> Option<bool> {
     }
 }
 
+/// Checks that the program can be registered under the name by both complete and compdef.
+fn check_program_name(name: &str, line_num: usize) -> Result<(), String> {
+    if is_valid_program_name(name) {
+        Ok(())
+    } else {
+        Err(format!("Invalid program name '{}' in line {}", name, line_num))
+    }
+}
+
+fn is_valid_program_name(name: &str) -> bool {
+    let special = |c: char| {
+        c.is_whitespace() || c.is_control() || "'\"`$\\;&|<>()[]{}*?#=~!".contains(c)
+    };
+    !name.is_empty() && !name.starts_with('-') && !name.contains(special)
+}
+
+fn is_valid_function_prefix(prefix: &str) -> bool {
+    !prefix.is_empty()
+        && !prefix.starts_with(|c: char| c.is_ascii_digit())
+        && prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_valid_env_var_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_valid_command_line_word(word: &str) -> bool {
+    !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c))
+}
+
+/// The directory that the file names in a configuration file are relative to.
+fn base_dir(cfg_filename: &str) -> &Path {
+    Path::new(cfg_filename).parent().unwrap_or_else(|| Path::new(""))
+}
+
+#[inline]
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..(value.len() - 1)]
+    } else {
+        value
+    }
+}
+
+/// Option names may use any number of '-' or '+' characters as their prefix
+/// (e.g -v, --verbose, -verbose, +x), but they must have a name after it.
+fn check_option_name(name: &str, line_num: usize) -> Result<String, String> {
+    let has_prefix = name.starts_with('-') || name.starts_with('+');
+    let has_name = !name.trim_start_matches(['-', '+']).is_empty();
+
+    if has_prefix && has_name && !name.contains(char::is_whitespace) {
+        Ok(name.to_owned())
+    } else {
+        Err(format!(
+            "Invalid option name '{}' in line {}. Option names must start with '-' or '+'",
+            name, line_num
+        ))
+    }
+}
+
 #[inline]
 fn boolean_value_error(field: &str, line_num: usize) -> String {
     format!("'{}' accepts only true or false (line {})", field, line_num)
 }
 
+/// Returns the byte range and the option name of each `{<option name>}` reference in a
+/// values command (e.g {--cluster}). Braces that don't enclose an option name are left alone.
+pub fn option_references(command: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut references = Vec::new();
+    let mut start = 0;
+    while let Some(open) = command[start..].find('{').map(|i| i + start) {
+        let close = match command[open..].find('}') {
+            Some(i) => i + open,
+            None => break,
+        };
+
+        let name = &command[(open + 1)..close];
+        if (name.starts_with('-') || name.starts_with('+')) && !name.contains(char::is_whitespace)
+        {
+            references.push((open..(close + 1), name));
+            start = close + 1;
+        } else {
+            start = open + 1;
+        }
+    }
+
+    references
+}
+
+/// Replaces each option reference in `command` with the result of `expand` for that option.
+pub fn expand_option_references(command: &str, expand: impl Fn(&str) -> String) -> String {
+    let mut expanded = String::new();
+    let mut last = 0;
+    for (range, name) in option_references(command) {
+        expanded.push_str(&command[last..range.start]);
+        expanded.push_str(&expand(name));
+        last = range.end;
+    }
+
+    expanded.push_str(&command[last..]);
+    expanded
+}
+
+/// Whether the commands of fixed_values_from_command fields are run while parsing. Only the
+/// scripts and the compiled files hold their values, so checking a configuration or completing
+/// a line with it leaves them without values instead of running every command each time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixedValuesCommands {
+    Run,
+    Skip,
+}
+
+/// What fields may refer to while parsing: the names declared anywhere in the configuration
+/// (so value types and options may be defined after they are used), the directory that
+/// relative file names are resolved against, the files that values are read from and
+/// whether the commands that values are read from are run.
+struct ParseContext {
+    value_types: Vec<String>,
+    option_names: Vec<String>,
+    base_dir: PathBuf,
+    fixed_values_files: Vec<PathBuf>,
+    commands: FixedValuesCommands,
+}
+
+impl ParseContext {
+    fn new(cfg_str: &str, base_dir: &Path, commands: FixedValuesCommands) -> Self {
+        let mut context = ParseContext {
+            value_types: Vec::new(),
+            option_names: Vec::new(),
+            base_dir: base_dir.to_owned(),
+            fixed_values_files: Vec::new(),
+            commands,
+        };
+
+        for line in cfg_str.lines() {
+            let mut colon_split = line.splitn(2, ':');
+            let field = colon_split.next().unwrap_or("").trim();
+            let value = colon_split.next().unwrap_or("").trim();
+
+            if let Some(name) = field.strip_prefix("value_type ") {
+                context.value_types.push(name.trim().to_owned());
+            } else if field == "short" || field == "long" {
+                context.option_names.push(value.to_owned());
+            } else if field == "aliases" {
+                let aliases = value.trim_start_matches('[').trim_end_matches(']');
+                context
+                    .option_names
+                    .extend(aliases.split(',').map(|alias| alias.trim().to_owned()));
+            } else if field == "fixed_values_file" {
+                context.fixed_values_files.push(base_dir.join(value));
+            }
+        }
+
+        context
+    }
+
+    fn check_option_references(&self, command: &str, line_num: usize) -> Result<(), String> {
+        match option_references(command)
+            .into_iter()
+            .find(|(_, name)| !self.option_names.iter().any(|n| n == name))
+        {
+            Some((_, name)) => Err(format!(
+                "Unknown option '{}' referenced in line {}",
+                name, line_num
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Fields that start a block of fields (e.g an option) and take no value themselves.
+#[inline]
+fn is_block_start(field: &str) -> bool {
+    field == "option" || field.starts_with("value_type ") || field.starts_with("env ")
+}
+
+/// Fields that end the block of fields before them.
+#[inline]
+fn ends_block(field: &str) -> bool {
+    is_block_start(field) || field == "section"
+}
+
 type FieldValueResult<'l> = Result<Option<(&'l str, &'l str, usize)>, String>;
 
 fn next_field_and_value_base(line: &str, line_num: usize) -> FieldValueResult<'_> {
     let line_num = line_num + 1;
-    let mut colon_split = line.split(':');
+    let mut colon_split = line.splitn(2, ':');
     if let (Some(field), Some(value)) = (colon_split.next(), colon_split.next()) {
         let field = field.trim();
         let value = value.trim();
 
         if field.is_empty() {
             return Err(format!("Missing field in line {}", line_num));
-        } else if value.is_empty() && field != "option" {
+        } else if value.is_empty() && !is_block_start(field) {
             return Err(format!("Missing value in line {}", line_num));
         }
 
@@ -115,15 +638,239 @@ fn next_field_and_value<'l>(line_it: &'l mut Peekable<Enumerate<Lines>>) -> Fiel
 }
 
 impl Config {
-    pub fn from_file(cfg_filename: &str) -> Result<Self, String> {
+    /// Returns warnings about parts of the configuration that are valid but likely need attention.
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mut seen_names: Vec<&str> = Vec::new();
+
+        for option in &self.program_options {
+            let names = option.names();
+            for name in &names {
+                if seen_names.contains(name) {
+                    warnings.push(format!("Option name '{}' is used by more than one option", name));
+                }
+            }
+            seen_names.extend(names.iter());
+
+            if let Some(message) = &option.deprecated {
+                let name = if option.long.is_empty() { &option.short } else { &option.long };
+                let message = if message.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", message)
+                };
+                let listed = if option.hidden {
+                    ""
+                } else {
+                    ". It is still listed, add 'hidden: true' to stop offering it"
+                };
+
+                warnings.push(format!("Option '{}' is deprecated{}{}", name, message, listed));
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns the name of the main completion function, which the names of the other generated
+    /// functions start with. Characters other than letters and digits are written as their hex
+    /// code between underscores (e.g my.tool becomes my_2e_tool) and `_` itself as `__`, so
+    /// different program names never share functions.
+    pub fn function_name(&self) -> String {
+        let mut name = self.function_prefix.clone();
+        for c in self.program_name.chars() {
+            match c {
+                '_' => name.push_str("__"),
+                c if c.is_ascii_alphanumeric() => name.push(c),
+                c => name.push_str(&format!("_{:x}_", c as u32)),
+            }
+        }
+
+        name
+    }
+
+    /// Returns the names the completion is registered for, program_name first.
+    pub fn command_names(&self) -> Vec<&str> {
+        let mut names = vec![self.program_name.as_str()];
+        for name in &self.program_names {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+
+        names
+    }
+
+    /// Returns the environment variables of the env blocks followed by the ones named by options,
+    /// which take the description and the values of their option.
+    pub fn all_env_vars(&self) -> Vec<EnvVar> {
+        let mut env_vars = self.env_vars.clone();
+        for option in self.program_options.iter().filter(|o| !o.env.is_empty()) {
+            if !env_vars.iter().any(|env_var| env_var.name == option.env) {
+                env_vars.push(EnvVar {
+                    name: option.env.clone(),
+                    description: option.description.replace("\\'", "'"),
+                    spec: option.value_spec(),
+                });
+            }
+        }
+
+        env_vars
+    }
+
+    /// Returns the categories of the listed options, in the order they first appear.
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories = Vec::new();
+        for option in self.program_options.iter().filter(|o| !o.hidden) {
+            if !option.category.is_empty() && !categories.contains(&option.category.as_str()) {
+                categories.push(option.category.as_str());
+            }
+        }
+
+        categories
+    }
+
+    pub fn option_named(&self, name: &str) -> Option<&ProgramOption> {
+        self.program_options
+            .iter()
+            .find(|option| option.names().contains(&name))
+    }
+
+    /// Whether any part of the line is handed over to the completion of another command.
+    pub fn delegates_command_line(&self) -> bool {
+        !self.command_line_after.is_empty()
+            || self.program_options.iter().any(|o| o.delegates_command_line())
+    }
+
+    /// Whether the output of any values command is cached.
+    pub fn uses_cache(&self) -> bool {
+        self.program_options.iter().any(|o| o.cache_ttl.is_some())
+            || self.value_types.iter().any(|t| match &t.spec {
+                ValueSpec::Command(command) => command.cache_ttl.is_some(),
+                _ => false,
+            })
+    }
+
+    /// Whether any values command refers to the value of another option.
+    pub fn uses_option_references(&self) -> bool {
+        self.program_options
+            .iter()
+            .map(|option| option.values_command.as_str())
+            .chain(self.value_types.iter().filter_map(|t| match &t.spec {
+                ValueSpec::Command(command) => Some(command.command.as_str()),
+                _ => None,
+            }))
+            .any(|command| !option_references(command).is_empty())
+    }
+
+    pub fn value_type(&self, name: &str) -> Option<&NamedValueType> {
+        self.value_types.iter().find(|value_type| value_type.name == name)
+    }
+
+    pub fn from_file(cfg_filename: &str, commands: FixedValuesCommands) -> Result<Self, String> {
         let cfg_contents = fs::read_to_string(cfg_filename)
             .map_err(|_| "Couldn't read configuration file.".to_string())?;
 
-        let cfg = Config::from_string(&cfg_contents)?;
+        let cfg = Config::parse(&cfg_contents, base_dir(cfg_filename), commands)?;
         Ok(cfg)
     }
 
+    /// Checks a configuration that wasn't parsed (e.g one decoded from a compiled file)
+    /// against the rules the parser enforces.
+    pub fn check(&self) -> Result<(), String> {
+        let mut program_names = std::iter::once(&self.program_name).chain(&self.program_names);
+        if let Some(name) = program_names.find(|n| !is_valid_program_name(n)) {
+            return Err(format!("Invalid program name '{}'", name));
+        }
+
+        if !is_valid_function_prefix(&self.function_prefix) {
+            return Err(format!("Invalid function prefix '{}'", self.function_prefix));
+        }
+
+        if !self.external_subcommands.is_empty()
+            && !is_valid_program_name(&self.external_subcommands)
+        {
+            return Err(format!(
+                "Invalid external subcommands prefix '{}'",
+                self.external_subcommands
+            ));
+        }
+
+        if let Some(word) = self
+            .command_line_after
+            .iter()
+            .find(|word| !is_valid_command_line_word(word))
+        {
+            return Err(format!("Invalid command_line_after word '{}'", word));
+        }
+
+        if self.program_options.is_empty() {
+            return Err("Configuration has no program options defined".to_string());
+        }
+
+        for (i, value_type) in self.value_types.iter().enumerate() {
+            if self.value_types[..i].iter().any(|t| t.name == value_type.name) {
+                return Err(format!(
+                    "Value type '{}' is declared more than once",
+                    value_type.name
+                ));
+            }
+        }
+
+        if let Some(env_var) = self.env_vars.iter().find(|v| !is_valid_env_var_name(&v.name)) {
+            return Err(format!("Invalid environment variable name '{}'", env_var.name));
+        }
+
+        let mut specs = Vec::new();
+        for option in &self.program_options {
+            option
+                .check()
+                .map_err(|e| format!("{} ({})", e, option.names().join(", ")))?;
+
+            specs.push(option.value_spec());
+            specs.extend(option.args.iter().map(|arg| arg.spec.clone()));
+            specs.extend(option.suboptions.iter().map(|arg| arg.spec.clone()));
+        }
+        specs.extend(self.value_types.iter().map(|t| t.spec.clone()));
+        specs.extend(self.env_vars.iter().map(|env_var| env_var.spec.clone()));
+
+        for spec in specs {
+            match spec {
+                ValueSpec::Named(name) if self.value_type(&name).is_none() => {
+                    return Err(format!("Unknown value type '{}'", name));
+                }
+                // The shells compare it with signed 64 bit arithmetic.
+                ValueSpec::Command(ValuesCommand {
+                    cache_ttl: Some(ttl),
+                    ..
+                }) if ttl == 0 || ttl > i64::MAX as u64 => {
+                    return Err(format!("'cache_ttl' of {} seconds is out of range", ttl));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the files that the configuration file `cfg_filename`, whose contents are
+    /// `cfg_str`, reads fixed values from.
+    pub fn referenced_files(cfg_str: &str, cfg_filename: &str) -> Vec<PathBuf> {
+        ParseContext::new(cfg_str, base_dir(cfg_filename), FixedValuesCommands::Skip)
+            .fixed_values_files
+    }
+
+    /// Parses the configuration, resolving relative file names against the current directory.
+    #[cfg(test)]
     pub fn from_string(cfg_str: &str) -> Result<Self, String> {
+        Config::parse(cfg_str, Path::new(""), FixedValuesCommands::Run)
+    }
+
+    fn parse(
+        cfg_str: &str,
+        base_dir: &Path,
+        commands: FixedValuesCommands,
+    ) -> Result<Self, String> {
         if cfg_str.is_empty() {
             return Err("Configuration is empty".to_string());
         }
@@ -131,24 +878,112 @@ impl Config {
         let mut cfg = Config::default();
         let mut line_it = cfg_str.lines().enumerate().peekable();
 
+        let context = ParseContext::new(cfg_str, base_dir, commands);
+        let mut section = String::new();
+
         while let Some((field, value, line_num)) = next_field_and_value(&mut line_it)? {
             match field {
                 "shell" => cfg.shell = value.to_owned(),
-                "program_name" => cfg.program_name = value.to_owned(),
+                "program_name" => {
+                    check_program_name(value, line_num)?;
+                    cfg.program_name = value.to_owned();
+                }
+                "program_names" => {
+                    cfg.program_names = Config::parse_list(field, value, line_num)?;
+                    for name in &cfg.program_names {
+                        check_program_name(name, line_num)?;
+                    }
+                }
+                "function_prefix" => {
+                    if !is_valid_function_prefix(value) {
+                        return Err(format!(
+                            "Invalid function prefix '{}' in line {}",
+                            value, line_num
+                        ));
+                    }
+
+                    cfg.function_prefix = value.to_owned();
+                }
+                "complete_paths" => {
+                    cfg.complete_paths =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?;
+                }
+                "complete_env_names" => {
+                    cfg.complete_env_names =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?;
+                }
                 "use_equals_sign" => {
                     cfg.use_equals_sign =
                         check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?;
                 }
+                "external_subcommands" => {
+                    // The prefix is matched literally in both shells, but it is also part of
+                    // their patterns and quoted strings.
+                    check_program_name(value, line_num).map_err(|_| {
+                        format!(
+                            "Invalid external subcommands prefix '{}' in line {}",
+                            value, line_num
+                        )
+                    })?;
+                    cfg.external_subcommands = value.to_owned();
+                }
+                "command_line_after" => {
+                    cfg.command_line_after = Config::parse_list(field, value, line_num)?;
+                    if let Some(word) = cfg
+                        .command_line_after
+                        .iter()
+                        .find(|word| !is_valid_command_line_word(word))
+                    {
+                        return Err(format!(
+                            "Invalid command_line_after word '{}' in line {}",
+                            word, line_num
+                        ));
+                    }
+                }
+                "section" => section = unquote(value).to_owned(),
                 "option" => {
-                    let program_option = Config::parse_program_option(&mut line_it)?;
+                    let mut program_option =
+                        Config::parse_program_option(&mut line_it, line_num, &context)?;
+                    if program_option.category.is_empty() {
+                        program_option.category = section.clone();
+                    }
+
                     cfg.program_options.push(program_option);
                 }
+                _ if field.starts_with("env ") => {
+                    let name = field["env ".len()..].trim().to_owned();
+                    let env_var = Config::parse_env_var(&mut line_it, &name, line_num, &context)?;
+                    cfg.env_vars.push(env_var);
+                }
+                _ if field.starts_with("value_type ") => {
+                    let name = field["value_type ".len()..].trim().to_owned();
+                    let value_type = Config::parse_named_value_type(
+                        &mut line_it,
+                        &name,
+                        line_num,
+                        &context,
+                    )?;
+                    if cfg.value_types.iter().any(|t| t.name == value_type.name) {
+                        return Err(format!(
+                            "Value type '{}' is declared more than once (line {})",
+                            name, line_num
+                        ));
+                    }
+
+                    cfg.value_types.push(value_type);
+                }
                 _ => {
                     return Err(format!("Unknown field '{}' in line {}", field, line_num));
                 }
             }
         }
 
+        if cfg.program_name.is_empty() {
+            if let Some(name) = cfg.program_names.first() {
+                cfg.program_name = name.clone();
+            }
+        }
+
         if cfg.program_name.is_empty() {
             return Err("Configuration is missing mandatory field 'program_name'".to_string());
         }
@@ -160,28 +995,190 @@ impl Config {
         Ok(cfg)
     }
 
-    fn parse_program_option(
+    fn parse_named_value_type(
         line_it: &mut Peekable<Enumerate<Lines>>,
-    ) -> Result<ProgramOption, String> {
-        let mut program_option = ProgramOption::default();
+        name: &str,
+        type_line_num: usize,
+        context: &ParseContext,
+    ) -> Result<NamedValueType, String> {
+        let is_valid_name = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && Config::parse_value_type(name, type_line_num, &[]).is_err();
+        if !is_valid_name {
+            return Err(format!(
+                "Invalid value type name '{}' in line {}",
+                name, type_line_num
+            ));
+        }
+
+        let mut spec = None;
+        let mut cache_ttl = None;
         while let Some((field, value, line_num)) = peek_field_and_value(line_it)? {
-            if field == "option" {
+            if ends_block(field) {
                 break;
             }
 
-            match field {
-                "short" => program_option.short = value.to_owned(),
-                "long" => program_option.long = value.to_owned(),
-                "description" => program_option.description = value.replace("'", "\\'"),
-                "accepts_files" => {
-                    program_option.accepts_files =
-                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
-                }
-                "accepts_multiple" => {
-                    program_option.accepts_multiple =
-                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
-                }
-                "accepts_value" => {
+            if field == "cache_ttl" {
+                cache_ttl = Some(Config::parse_duration(value, line_num)?);
+                line_it.next();
+                continue;
+            }
+
+            let field_spec = match Config::parse_values_field(field, value, line_num, context)? {
+                Some(field_spec) => field_spec,
+                None => return Err(format!("Unknown field '{}' in line {}", field, line_num)),
+            };
+
+            if spec.is_some() {
+                return Err(format!(
+                    "Value type '{}' accepts only one of fixed_values, values_command or files (line {})",
+                    name, line_num
+                ));
+            }
+
+            spec = Some(field_spec);
+            line_it.next();
+        }
+
+        if let Some(ttl) = cache_ttl {
+            match &mut spec {
+                Some(ValueSpec::Command(command)) => command.cache_ttl = Some(ttl),
+                _ => {
+                    return Err(format!(
+                        "'cache_ttl' requires a values_command (line {})",
+                        type_line_num
+                    ))
+                }
+            }
+        }
+
+        match spec {
+            Some(spec) => Ok(NamedValueType {
+                name: name.to_owned(),
+                spec,
+            }),
+            None => Err(format!(
+                "Value type '{}' has no values (line {})",
+                name, type_line_num
+            )),
+        }
+    }
+
+    /// Parses the fields that give the values of a value type or an environment variable.
+    /// Returns None if the field isn't one of them.
+    fn parse_values_field(
+        field: &str,
+        value: &str,
+        line_num: usize,
+        context: &ParseContext,
+    ) -> Result<Option<ValueSpec>, String> {
+        let spec = match field {
+            "fixed_values" => ValueSpec::Fixed(Config::parse_fixed_values(value, line_num)?),
+            "fixed_values_file" | "fixed_values_from_command" => {
+                ValueSpec::Fixed(Config::resolve_fixed_values(field, value, line_num, context)?)
+            }
+            "values_command" => {
+                context.check_option_references(value, line_num)?;
+                ValueSpec::Command(ValuesCommand {
+                    command: value.to_owned(),
+                    cache_ttl: None,
+                })
+            }
+            "files" => ValueSpec::Files(Config::parse_file_filter(value, line_num)?),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(spec))
+    }
+
+    fn parse_env_var(
+        line_it: &mut Peekable<Enumerate<Lines>>,
+        name: &str,
+        var_line_num: usize,
+        context: &ParseContext,
+    ) -> Result<EnvVar, String> {
+        if !is_valid_env_var_name(name) {
+            return Err(format!(
+                "Invalid environment variable name '{}' in line {}",
+                name, var_line_num
+            ));
+        }
+
+        let mut env_var = EnvVar {
+            name: name.to_owned(),
+            description: String::new(),
+            spec: ValueSpec::Any,
+        };
+
+        while let Some((field, value, line_num)) = peek_field_and_value(line_it)? {
+            if ends_block(field) {
+                break;
+            }
+
+            if field == "description" {
+                env_var.description = value.to_owned();
+            } else if field == "value_type" {
+                env_var.spec = Config::parse_value_type(value, line_num, &context.value_types)?;
+            } else if let Some(spec) = Config::parse_values_field(field, value, line_num, context)? {
+                env_var.spec = spec;
+            } else {
+                return Err(format!("Unknown field '{}' in line {}", field, line_num));
+            }
+
+            line_it.next();
+        }
+
+        Ok(env_var)
+    }
+
+    fn parse_program_option(
+        line_it: &mut Peekable<Enumerate<Lines>>,
+        option_line_num: usize,
+        context: &ParseContext,
+    ) -> Result<ProgramOption, String> {
+        let value_type_names = &context.value_types;
+        let mut program_option = ProgramOption::default();
+        while let Some((field, value, line_num)) = peek_field_and_value(line_it)? {
+            if ends_block(field) {
+                break;
+            }
+
+            match field {
+                "short" => program_option.short = check_option_name(value, line_num)?,
+                "long" => program_option.long = check_option_name(value, line_num)?,
+                "description" => program_option.description = value.replace("'", "\\'"),
+                "accepts_files" => {
+                    program_option.accepts_files =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
+                }
+                "files" => {
+                    program_option.accepts_files = true;
+                    program_option.file_filter = Config::parse_file_filter(value, line_num)?;
+                }
+                "value_type" => {
+                    match Config::parse_value_type(value, line_num, value_type_names)? {
+                        ValueSpec::Files(filter) => {
+                            program_option.accepts_files = true;
+                            program_option.file_filter = filter;
+                        }
+                        ValueSpec::Kind(kind) => program_option.value_kind = Some(kind),
+                        ValueSpec::Named(name) => program_option.value_type_name = name,
+                        _ => unreachable!(),
+                    }
+                }
+                "values_command" => {
+                    context.check_option_references(value, line_num)?;
+                    program_option.values_command = value.to_owned();
+                }
+                "cache_ttl" => {
+                    program_option.cache_ttl = Some(Config::parse_duration(value, line_num)?)
+                }
+                "terminator" => program_option.terminator = unquote(value).to_owned(),
+                "accepts_multiple" => {
+                    program_option.accepts_multiple =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
+                }
+                "accepts_value" => {
                     program_option.accepts_value =
                         check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
                 }
@@ -189,27 +1186,328 @@ impl Config {
                     let fixed_values = Config::parse_fixed_values(value, line_num)?;
                     program_option.fixed_values = fixed_values;
                 }
+                "fixed_values_file" | "fixed_values_from_command" => {
+                    program_option.fixed_values =
+                        Config::resolve_fixed_values(field, value, line_num, context)?;
+                }
+                "arity" => {
+                    program_option.arity = Some(Config::parse_arity(value, line_num)?);
+                }
+                "arg" => {
+                    let arg = Config::parse_option_arg(value, line_num, value_type_names)?;
+                    program_option.args.push(arg);
+                }
+                "negatable" => {
+                    program_option.negatable =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
+                }
+                "aliases" => {
+                    program_option.aliases = Config::parse_list(field, value, line_num)?
+                        .iter()
+                        .map(|alias| check_option_name(alias, line_num))
+                        .collect::<Result<_, _>>()?;
+                }
+                "hide_aliases" => {
+                    program_option.hide_aliases =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
+                }
+                "attached_value" => {
+                    program_option.attached_value = match value {
+                        "false" => AttachedValue::No,
+                        "true" => AttachedValue::Allowed,
+                        "only" => AttachedValue::Required,
+                        _ => {
+                            return Err(format!(
+                                "'{}' accepts only true, false or only (line {})",
+                                field, line_num
+                            ))
+                        }
+                    };
+                }
+                "value_separator" => {
+                    program_option.value_separator = unquote(value).to_owned();
+                }
+                "hidden" => {
+                    program_option.hidden =
+                        check_bool(value).ok_or_else(|| boolean_value_error(field, line_num))?
+                }
+                "category" => program_option.category = unquote(value).to_owned(),
+                "value_name" => program_option.value_name = unquote(value).to_owned(),
+                "default" => program_option.default_value = unquote(value).to_owned(),
+                "hint" => program_option.hint = unquote(value).to_owned(),
+                "env" => program_option.env = value.to_owned(),
+                "deprecated" => {
+                    program_option.deprecated = match value {
+                        "false" => None,
+                        "true" => Some(String::new()),
+                        message => Some(unquote(message).to_owned()),
+                    };
+                }
+                "suboption" => {
+                    let suboption = Config::parse_option_arg(value, line_num, value_type_names)?;
+                    program_option.suboptions.push(suboption);
+                }
                 _ => return Err(format!("Unknown field '{}' in line {}", field, line_num)),
             }
 
             line_it.next();
         }
 
+        if program_option.arity.is_none() && !program_option.args.is_empty() {
+            let count = program_option.args.len();
+            program_option.arity = Some(Arity {
+                min: count,
+                max: count,
+            });
+        }
+
+        if !program_option.suboptions.is_empty() && program_option.value_separator.is_empty() {
+            program_option.value_separator = ",".to_owned();
+        }
+
+        program_option
+            .check()
+            .map_err(|e| format!("{} (line {})", e, option_line_num))?;
+
         Ok(program_option)
     }
 
+    /// Parses a number of seconds, optionally followed by one of the s, m, h or d units.
+    fn parse_duration(duration: &str, line_num: usize) -> Result<u64, String> {
+        let (count, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => duration.split_at(i),
+            None => (duration, "s"),
+        };
+
+        let multiplier = match unit {
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(60 * 60),
+            "d" => Some(24 * 60 * 60),
+            _ => None,
+        };
+
+        let too_large = || format!("'cache_ttl' is too large (line {})", line_num);
+
+        match (count.parse::<u64>(), multiplier) {
+            (Ok(count), Some(multiplier)) if count > 0 => count
+                .checked_mul(multiplier)
+                // The shells compare it with signed 64 bit arithmetic.
+                .filter(|ttl| *ttl <= i64::MAX as u64)
+                .ok_or_else(too_large),
+            (Err(e), Some(_)) if *e.kind() == IntErrorKind::PosOverflow => Err(too_large()),
+            _ => Err(format!(
+                "'cache_ttl' has incorrect format. Expected <count>[s|m|h|d] (line {})",
+                line_num
+            )),
+        }
+    }
+
+    fn parse_arity(arity: &str, line_num: usize) -> Result<Arity, String> {
+        let format_error = || {
+            format!(
+                "'arity' has incorrect format. Expected <count> or <min>..<max> (line {})",
+                line_num
+            )
+        };
+
+        let (min, max) = match arity.find("..") {
+            Some(pos) => (&arity[..pos], &arity[(pos + 2)..]),
+            None => (arity, arity),
+        };
+
+        let min = min.trim().parse::<usize>().map_err(|_| format_error())?;
+        let max = max.trim().parse::<usize>().map_err(|_| format_error())?;
+        if max == 0 || min > max {
+            return Err(format_error());
+        }
+
+        Ok(Arity { min, max })
+    }
+
+    fn parse_option_arg(
+        arg: &str,
+        line_num: usize,
+        value_type_names: &[String],
+    ) -> Result<OptionArg, String> {
+        let (name, spec) = match arg.find(char::is_whitespace) {
+            Some(pos) => (&arg[..pos], arg[pos..].trim()),
+            None => (arg, ""),
+        };
+
+        let spec = if spec.is_empty() {
+            ValueSpec::Any
+        } else if spec == "files" {
+            ValueSpec::Files(FileFilter::default())
+        } else if spec.starts_with("files(") && spec.ends_with(')') {
+            let filter = &spec[("files(".len())..(spec.len() - 1)];
+            ValueSpec::Files(Config::parse_file_filter(filter, line_num)?)
+        } else if spec.starts_with('[') {
+            ValueSpec::Fixed(Config::parse_fixed_values(spec, line_num)?)
+        } else {
+            Config::parse_value_type(spec, line_num, value_type_names).map_err(|_| {
+                format!(
+                    "Unknown value specification '{}' in line {}",
+                    spec, line_num
+                )
+            })?
+        };
+
+        if spec == ValueSpec::Kind(ValueKind::CommandLine) {
+            return Err(format!(
+                "'command_line' can only be used as the value_type of an option (line {})",
+                line_num
+            ));
+        }
+
+        Ok(OptionArg {
+            name: name.to_owned(),
+            spec,
+        })
+    }
+
+    fn parse_value_type(
+        value_type: &str,
+        line_num: usize,
+        value_type_names: &[String],
+    ) -> Result<ValueSpec, String> {
+        let files = |kind| {
+            ValueSpec::Files(FileFilter {
+                kind,
+                ..FileFilter::default()
+            })
+        };
+
+        let spec = match value_type {
+            "path" => files(FileKind::All),
+            "dir" => files(FileKind::Directories),
+            "executable" => files(FileKind::Executables),
+            "command" => ValueSpec::Kind(ValueKind::Command),
+            "user" => ValueSpec::Kind(ValueKind::User),
+            "group" => ValueSpec::Kind(ValueKind::Group),
+            "host" => ValueSpec::Kind(ValueKind::Host),
+            "port" | "service" => ValueSpec::Kind(ValueKind::Port),
+            "pid" => ValueSpec::Kind(ValueKind::Pid),
+            "signal" => ValueSpec::Kind(ValueKind::Signal),
+            "env_var" => ValueSpec::Kind(ValueKind::EnvVar),
+            "interface" => ValueSpec::Kind(ValueKind::Interface),
+            "locale" => ValueSpec::Kind(ValueKind::Locale),
+            "timezone" => ValueSpec::Kind(ValueKind::Timezone),
+            "command_line" => ValueSpec::Kind(ValueKind::CommandLine),
+            name if value_type_names.iter().any(|n| n == name) => {
+                ValueSpec::Named(name.to_owned())
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown value type '{}' in line {}",
+                    value_type, line_num
+                ))
+            }
+        };
+
+        Ok(spec)
+    }
+
+    fn parse_file_filter(filter: &str, line_num: usize) -> Result<FileFilter, String> {
+        let mut file_filter = FileFilter::default();
+        for word in filter.split_whitespace() {
+            let kind = match word {
+                "all" => FileKind::All,
+                "dirs" => FileKind::Directories,
+                "executables" => FileKind::Executables,
+                "no_hidden" => {
+                    file_filter.include_hidden = false;
+                    continue;
+                }
+                glob => FileKind::Glob(glob.to_owned()),
+            };
+
+            if file_filter.kind != FileKind::All {
+                return Err(format!(
+                    "'files' accepts only one of all, dirs, executables or a glob (line {})",
+                    line_num
+                ));
+            }
+
+            file_filter.kind = kind;
+        }
+
+        Ok(file_filter)
+    }
+
     fn parse_fixed_values(fixed_values: &str, line_num: usize) -> Result<Vec<String>, String> {
-        if let (Some(start), Some(end)) = (fixed_values.find('['), fixed_values.find(']')) {
-            let fixed_values = &fixed_values[(start + 1)..end];
-            let fixed_values: Vec<_> = fixed_values
-                .split(',')
-                .map(|v| v.trim().replace("'", "\\'"))
-                .collect();
-
-            Ok(fixed_values)
+        let fixed_values = Config::parse_list("fixed_values", fixed_values, line_num)?;
+        Ok(Config::escape_fixed_values(fixed_values))
+    }
+
+    #[inline]
+    fn escape_fixed_values(fixed_values: Vec<String>) -> Vec<String> {
+        fixed_values
+            .into_iter()
+            .map(|v| v.replace("'", "\\'"))
+            .collect()
+    }
+
+    /// Reads the fixed values of a fixed_values_file or fixed_values_from_command field,
+    /// one value per non empty line. Files and commands are resolved relative to the
+    /// directory of the configuration file. Commands that aren't run give no values.
+    fn resolve_fixed_values(
+        field: &str,
+        value: &str,
+        line_num: usize,
+        context: &ParseContext,
+    ) -> Result<Vec<String>, String> {
+        let contents = if field == "fixed_values_file" {
+            fs::read_to_string(context.base_dir.join(value)).map_err(|e| {
+                format!(
+                    "Couldn't read fixed values file '{}': {} (line {})",
+                    value, e, line_num
+                )
+            })?
+        } else if context.commands == FixedValuesCommands::Skip {
+            String::new()
+        } else {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(value)
+                .current_dir(if context.base_dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    &context.base_dir
+                })
+                .output()
+                .map_err(|e| format!("Couldn't run '{}': {} (line {})", value, e, line_num))?;
+
+            if !output.status.success() {
+                return Err(format!(
+                    "Command '{}' failed with {} (line {})",
+                    value, output.status, line_num
+                ));
+            }
+
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        let fixed_values = contents
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        Ok(Config::escape_fixed_values(fixed_values))
+    }
+
+    fn parse_list(field: &str, list: &str, line_num: usize) -> Result<Vec<String>, String> {
+        if let (Some(start), Some(end)) = (list.find('['), list.find(']')) {
+            let list = &list[(start + 1)..end];
+            let list: Vec<_> = list.split(',').map(|v| v.trim().to_owned()).collect();
+
+            Ok(list)
         } else {
             Err(format!(
-                "'fixed_values' has incorrect format. Expected [<fixed_value>, ...] (line {})",
+                "'{}' has incorrect format. Expected [<{}>, ...] (line {})",
+                field,
+                field.trim_end_matches('s'),
                 line_num
             ))
         }
@@ -347,6 +1645,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn missing_fields_are_set_to_default() {
         let cfg_str = "\
             shell: bash
@@ -369,6 +1668,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn missing_fields_are_set_to_default_extra() {
         let cfg_str = "\
             program_name: test
@@ -404,6 +1704,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn config_parses_correctly_1() {
         let cfg_str = "\
             program_name: test_2
@@ -484,4 +1785,914 @@ mod tests {
             vec!["don\\'t", "it\\'s"]
         );
     }
+
+    #[test]
+    fn options_with_arity_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --point
+                arity: 2
+                arg: x [0, 1]
+            option:
+                long: --range
+                arity: 1..2
+                arg: start
+                arg: end files
+            option:
+                long: --pair
+                arg: key
+                arg: value
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let point = &cfg.program_options[0];
+        assert_eq!(point.arity, Some(Arity { min: 2, max: 2 }));
+        assert_eq!(point.arg_at(0).name, "x");
+        assert_eq!(
+            point.arg_at(0).spec,
+            ValueSpec::Fixed(vec!["0".to_owned(), "1".to_owned()])
+        );
+        assert_eq!(point.arg_at(1).name, "");
+        assert_eq!(point.arg_at(1).spec, ValueSpec::Any);
+
+        let range = &cfg.program_options[1];
+        assert_eq!(range.arity, Some(Arity { min: 1, max: 2 }));
+        assert_eq!(range.arg_at(1).spec, ValueSpec::Files(FileFilter::default()));
+
+        let pair = &cfg.program_options[2];
+        assert_eq!(pair.arity, Some(Arity { min: 2, max: 2 }));
+    }
+
+    #[test]
+    fn invalid_arity_should_fail() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --range
+                arity: 3..1
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "'arity' has incorrect format. Expected <count> or <min>..<max> (line 4)"
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --point
+                arity: 1
+                arg: x
+                arg: y
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Option defines more 'arg' entries than its arity allows (line 2)"
+        );
+    }
+
+    #[test]
+    fn value_lists_and_suboptions_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --features
+                value_separator: \":\"
+                fixed_values: [a, b, c]
+            option:
+                short: -o
+                suboption: ro
+                suboption: uid [1000, 1001]
+                suboption: mode [0644, 0755]
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let features = &cfg.program_options[0];
+        assert!(features.takes_value_list());
+        assert_eq!(features.value_separator, ":");
+        assert!(features.suboptions.is_empty());
+
+        let mount_options = &cfg.program_options[1];
+        assert_eq!(mount_options.value_separator, ",");
+        assert_eq!(mount_options.suboptions.len(), 3);
+        assert_eq!(mount_options.suboptions[0].name, "ro");
+        assert_eq!(mount_options.suboptions[0].spec, ValueSpec::Any);
+        assert_eq!(
+            mount_options.suboptions[2].spec,
+            ValueSpec::Fixed(vec!["0644".to_owned(), "0755".to_owned()])
+        );
+    }
+
+    #[test]
+    fn negatable_options_derive_their_negated_form() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -c
+                long: --color
+                accepts_value: false
+                negatable: true
+            option:
+                long: --quiet
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let negated = cfg.program_options[0].negated();
+        assert!(negated.is_some());
+        let negated = negated.unwrap();
+        assert_eq!(negated.long, "--no-color");
+        assert_eq!(negated.short, "");
+        assert_eq!(negated.description, "Negate --color");
+        assert!(!negated.accepts_value);
+        assert!(cfg.program_options[1].negated().is_none());
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -c
+                negatable: true
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Negatable option must have a long name (line 2)"
+        );
+    }
+
+    #[test]
+    fn aliases_are_names_of_the_same_option() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -h
+                long: --help
+                aliases: [-?, --usage]
+                hide_aliases: true
+            option:
+                long: --color
+                aliases: [--colour]
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let help = &cfg.program_options[0];
+        assert_eq!(help.names(), vec!["-h", "--help", "-?", "--usage"]);
+        assert_eq!(help.visible_names(), vec!["-h", "--help"]);
+        assert_eq!(help.hidden_aliases(), ["-?", "--usage"]);
+
+        let color = &cfg.program_options[1];
+        assert!(!color.has_one_representation());
+        assert_eq!(color.visible_names(), vec!["--color", "--colour"]);
+        assert!(color.hidden_aliases().is_empty());
+    }
+
+    #[test]
+    fn option_names_accept_any_prefix() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: +x
+                long: -verbose
+                negatable: true
+            option:
+                short: -Xmx
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+        assert_eq!(cfg.program_options[0].names(), vec!["+x", "-verbose"]);
+        assert_eq!(cfg.program_options[0].negated().unwrap().long, "-no-verbose");
+        assert_eq!(cfg.program_options[1].short, "-Xmx");
+    }
+
+    #[test]
+    fn option_names_without_prefix_should_fail() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: verbose
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Invalid option name 'verbose' in line 3. Option names must start with '-' or '+'"
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -h
+                aliases: [-?, --]
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Invalid option name '--' in line 4. Option names must start with '-' or '+'"
+        );
+    }
+
+    #[test]
+    fn attached_value_parses_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -o
+                long: --output
+                accepts_files: true
+                attached_value: true
+            option:
+                short: -O
+                fixed_values: [0, 1, 2, 3]
+                attached_value: only
+            option:
+                short: -v
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+        assert_eq!(cfg.program_options[0].attached_value, AttachedValue::Allowed);
+        assert_eq!(cfg.program_options[1].attached_value, AttachedValue::Required);
+        assert_eq!(cfg.program_options[2].attached_value, AttachedValue::No);
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --jobs
+                attached_value: true
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Option with 'attached_value' must have a short name and accept a value (line 2)"
+        );
+    }
+
+    #[test]
+    fn file_filters_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --manifest
+                files: *.toml no_hidden
+            option:
+                long: --dir
+                files: dirs
+            option:
+                long: --point
+                arg: script files(executables)
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let manifest = &cfg.program_options[0];
+        assert!(manifest.accepts_files);
+        assert_eq!(
+            manifest.value_spec(),
+            ValueSpec::Files(FileFilter {
+                kind: FileKind::Glob("*.toml".to_owned()),
+                include_hidden: false,
+            })
+        );
+
+        assert_eq!(cfg.program_options[1].file_filter.kind, FileKind::Directories);
+        assert_eq!(
+            cfg.program_options[2].arg_at(0).spec,
+            ValueSpec::Files(FileFilter {
+                kind: FileKind::Executables,
+                include_hidden: true,
+            })
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --manifest
+                files: dirs *.toml
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "'files' accepts only one of all, dirs, executables or a glob (line 4)"
+        );
+    }
+
+    #[test]
+    fn value_types_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --user
+                value_type: user
+            option:
+                long: --workdir
+                value_type: dir
+            option:
+                long: --kill
+                arg: pid pid
+                arg: signal signal
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(
+            cfg.program_options[0].value_spec(),
+            ValueSpec::Kind(ValueKind::User)
+        );
+        assert!(cfg.program_options[1].accepts_files);
+        assert_eq!(
+            cfg.program_options[1].file_filter.kind,
+            FileKind::Directories
+        );
+        assert_eq!(
+            cfg.program_options[2].arg_at(1).spec,
+            ValueSpec::Kind(ValueKind::Signal)
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --user
+                value_type: person
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Unknown value type 'person' in line 4");
+    }
+
+    #[test]
+    fn command_line_options_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --wrap
+                value_type: command_line
+            option:
+                long: -exec
+                value_type: command_line
+                terminator: \";\"
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+        assert!(cfg.program_options[0].delegates_command_line());
+        assert_eq!(cfg.program_options[0].terminator, "");
+        assert_eq!(cfg.program_options[1].terminator, ";");
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --files
+                terminator: ;
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "'terminator' is only valid for options with a command_line value (line 2)"
+        );
+    }
+
+    #[test]
+    fn command_line_after_words_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            command_line_after: [--, exec]
+            option:
+                long: --verbose
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+        assert_eq!(cfg.command_line_after, ["--", "exec"]);
+        assert!(cfg.delegates_command_line());
+
+        let cfg_str = "\
+            program_name: test
+            command_line_after: [--, ]
+            option:
+                long: --verbose
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Invalid command_line_after word '' in line 2");
+    }
+
+    #[test]
+    fn external_subcommands_prefix_is_parsed() {
+        let cfg_str = "\
+            program_name: ourtool
+            external_subcommands: ourtool-
+            option:
+                long: --help
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        assert_eq!(cfg.unwrap().external_subcommands, "ourtool-");
+
+        let cfg_str = "\
+            program_name: ourtool
+            external_subcommands: our*tool-
+            option:
+                long: --help
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Invalid external subcommands prefix 'our*tool-' in line 2"
+        );
+    }
+
+    #[test]
+    fn named_value_types_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --env
+                value_type: environments
+            option:
+                long: --promote
+                arg: from environments
+                arg: to environments
+            option:
+                long: --branch
+                values_command: git branch --format=%(refname:short)
+            value_type environments:
+                fixed_values: [dev, staging, prod]
+            value_type manifests:
+                files: *.toml
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(cfg.value_types.len(), 2);
+        assert_eq!(
+            cfg.value_type("environments").unwrap().spec,
+            ValueSpec::Fixed(vec![
+                "dev".to_owned(),
+                "staging".to_owned(),
+                "prod".to_owned()
+            ])
+        );
+        assert_eq!(
+            cfg.program_options[0].value_spec(),
+            ValueSpec::Named("environments".to_owned())
+        );
+        assert_eq!(
+            cfg.program_options[1].arg_at(1).spec,
+            ValueSpec::Named("environments".to_owned())
+        );
+        assert_eq!(
+            cfg.program_options[2].value_spec(),
+            ValueSpec::Command(ValuesCommand {
+                command: "git branch --format=%(refname:short)".to_owned(),
+                cache_ttl: None
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_named_value_types_should_fail() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --env
+            value_type envs:
+                fixed_values: [dev]
+                files: all
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Value type 'envs' accepts only one of fixed_values, values_command or files (line 6)"
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --env
+            value_type user:
+                fixed_values: [me]
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Invalid value type name 'user' in line 4");
+    }
+
+    #[test]
+    fn values_commands_reference_other_options() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                short: -c
+                long: --cluster
+            option:
+                long: --namespace
+                values_command: ourtool ns list --cluster {--cluster} | awk '{print $1}'
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let command = &cfg.program_options[1].values_command;
+        let references = option_references(command);
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].1, "--cluster");
+        assert_eq!(cfg.option_named("--cluster").unwrap().short, "-c");
+        assert_eq!(
+            expand_option_references(command, |name| format!("$({})", name)),
+            "ourtool ns list --cluster $(--cluster) | awk '{print $1}'"
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --namespace
+                values_command: ourtool ns list --cluster {--cluster}
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Unknown option '--cluster' referenced in line 4"
+        );
+    }
+
+    #[test]
+    fn cache_ttl_parses_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --namespace
+                values_command: ourtool ns list
+                cache_ttl: 5m
+            option:
+                long: --pod
+                value_type: pods
+            value_type pods:
+                values_command: ourtool pod list
+                cache_ttl: 90
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(cfg.program_options[0].cache_ttl, Some(300));
+        assert_eq!(
+            cfg.value_type("pods").unwrap().spec,
+            ValueSpec::Command(ValuesCommand {
+                command: "ourtool pod list".to_owned(),
+                cache_ttl: Some(90)
+            })
+        );
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --namespace
+                values_command: ourtool ns list
+                cache_ttl: 5 minutes
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "'cache_ttl' has incorrect format. Expected <count>[s|m|h|d] (line 5)"
+        );
+
+        for ttl in ["99999999999999999999d", "999999999999999999d"] {
+            let cfg_str = format!(
+                "\
+                program_name: test
+                option:
+                    long: --namespace
+                    values_command: ourtool ns list
+                    cache_ttl: {}
+                ",
+                ttl
+            );
+
+            let cfg = Config::from_string(&cfg_str);
+            assert!(cfg.is_err());
+            assert_eq!(cfg.unwrap_err(), "'cache_ttl' is too large (line 5)");
+        }
+    }
+
+    #[test]
+    fn fixed_values_are_read_from_files_and_commands() {
+        let dir = std::env::temp_dir().join(format!(
+            "autocshell_fixed_values_test_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("targets.txt"), "x86_64\n\naarch64\n").unwrap();
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --target
+                fixed_values_file: targets.txt
+            option:
+                long: --format
+                value_type: formats
+            value_type formats:
+                fixed_values_from_command: printf 'json\\nyaml\\n'
+        ";
+        fs::write(dir.join("spec.cfg"), cfg_str).unwrap();
+
+        let cfg = Config::from_file(
+            dir.join("spec.cfg").to_str().unwrap(),
+            FixedValuesCommands::Run,
+        );
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(cfg.program_options[0].fixed_values, ["x86_64", "aarch64"]);
+        assert_eq!(
+            cfg.value_type("formats").unwrap().spec,
+            ValueSpec::Fixed(vec!["json".to_owned(), "yaml".to_owned()])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --format
+                fixed_values_from_command: exit 1
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Command 'exit 1' failed with exit status: 1 (line 4)"
+        );
+
+        let cfg = Config::parse(cfg_str, Path::new(""), FixedValuesCommands::Skip);
+        assert!(cfg.is_ok());
+        assert!(cfg.unwrap().program_options[0].fixed_values.is_empty());
+    }
+
+    #[test]
+    fn hidden_and_deprecated_options_parse_and_lint() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --old
+                description: Old behaviour
+                deprecated: \"use --new instead\"
+            option:
+                long: --legacy
+                hidden: true
+                deprecated: true
+            option:
+                short: -n
+                long: --new
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(
+            cfg.program_options[0].listed_description(),
+            "Old behaviour (deprecated: use --new instead)"
+        );
+        assert!(cfg.program_options[1].hidden);
+        assert_eq!(cfg.program_options[1].listed_description(), "deprecated");
+        assert_eq!(cfg.program_options[2].deprecated, None);
+
+        assert_eq!(
+            cfg.lint(),
+            [
+                "Option '--old' is deprecated (use --new instead). It is still listed, add 'hidden: true' to stop offering it",
+                "Option '--legacy' is deprecated"
+            ]
+        );
+    }
+
+    #[test]
+    fn options_are_grouped_in_categories() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --help
+            section: Output
+            option:
+                long: --color
+            option:
+                long: --proxy
+                category: Network
+            option:
+                long: --quiet
+            section: \"Network\"
+            option:
+                long: --timeout
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        let categories = cfg
+            .program_options
+            .iter()
+            .map(|o| o.category.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(categories, ["", "Output", "Network", "Output", "Network"]);
+        assert_eq!(cfg.categories(), ["Output", "Network"]);
+    }
+
+    #[test]
+    fn value_descriptions_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --port
+                value_name: PORT
+                default: 8080
+                hint: \"TCP port (1-65535)\"
+            option:
+                long: --name
+                hint: Any name
+            option:
+                long: --quiet
+                accepts_value: false
+                default: true
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Option with 'value_name', 'default' or 'hint' must accept a value (line 10)"
+        );
+
+        let cfg = Config::from_string(&cfg_str.replace("default: true", ""));
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(
+            cfg.program_options[0].value_description(),
+            "PORT: TCP port (1-65535) (default: 8080)"
+        );
+        assert_eq!(cfg.program_options[1].value_description(), "Any name");
+    }
+
+    #[test]
+    fn env_vars_parse_correctly() {
+        let cfg_str = "\
+            program_name: test
+            option:
+                long: --color
+                description: Use the tool's colours
+                env: OURTOOL_COLOR
+                fixed_values: [auto, always, never]
+            env OURTOOL_HOME:
+                description: Where ourtool keeps its data
+                value_type: dir
+            env OURTOOL_LOG:
+                fixed_values: [debug, info]
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(
+            cfg.program_options[0].listed_description(),
+            "Use the tool\\'s colours (env: OURTOOL_COLOR)"
+        );
+        assert!(!cfg.complete_env_names);
+
+        let env_vars = cfg.all_env_vars();
+        assert_eq!(env_vars.len(), 3);
+        assert_eq!(env_vars[0].name, "OURTOOL_HOME");
+        assert!(matches!(
+            &env_vars[0].spec,
+            ValueSpec::Files(filter) if filter.kind == FileKind::Directories
+        ));
+        assert_eq!(
+            env_vars[1].spec,
+            ValueSpec::Fixed(vec!["debug".to_owned(), "info".to_owned()])
+        );
+        assert_eq!(env_vars[2].name, "OURTOOL_COLOR");
+        assert_eq!(env_vars[2].description, "Use the tool's colours");
+
+        let cfg = Config::from_string(&cfg_str.replace("env OURTOOL_LOG", "env 1LOG"));
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Invalid environment variable name '1LOG' in line 10"
+        );
+
+        let cfg = Config::from_string(&cfg_str.replace("env: OURTOOL_COLOR", "env: OURTOOL-COLOR"));
+        assert!(cfg.is_err());
+        assert_eq!(
+            cfg.unwrap_err(),
+            "Invalid environment variable name in 'env' (line 2)"
+        );
+
+        let cfg = Config::from_string(&format!("complete_env_names: true\n{}", cfg_str));
+        assert!(cfg.is_ok());
+        assert!(cfg.unwrap().complete_env_names);
+    }
+
+    #[test]
+    fn program_names_register_every_name() {
+        let cfg_str = "\
+            shell: bash
+            program_names: [ourtool, ourtool-dev, ourtool]
+            complete_paths: true
+            option:
+                long: --help
+        ";
+
+        let cfg = Config::from_string(cfg_str);
+        assert!(cfg.is_ok());
+        let cfg = cfg.unwrap();
+
+        assert_eq!(cfg.program_name, "ourtool");
+        assert_eq!(cfg.command_names(), vec!["ourtool", "ourtool-dev"]);
+        assert!(cfg.complete_paths);
+
+        let cfg = Config::from_string(&cfg_str.replace("ourtool-dev", "our tool"));
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Invalid program name 'our tool' in line 2");
+    }
+
+    #[test]
+    fn function_names_are_sanitised() {
+        let cfg_str = "\
+            shell: bash
+            program_name: my.tool
+            option:
+                long: --help
+        ";
+
+        let function_name = |cfg_str: &str| Config::from_string(cfg_str).unwrap().function_name();
+        assert_eq!(function_name(cfg_str), "_my_2e_tool");
+        assert_eq!(function_name(&cfg_str.replace("my.tool", "foo-bar")), "_foo_2d_bar");
+        assert_eq!(function_name(&cfg_str.replace("my.tool", "foo_bar")), "_foo__bar");
+        assert_eq!(
+            function_name(&cfg_str.replace("bash", "bash\nfunction_prefix: _acs_")),
+            "_acs_my_2e_tool"
+        );
+
+        let cfg = Config::from_string(&cfg_str.replace("my.tool", "my;tool"));
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Invalid program name 'my;tool' in line 2");
+
+        let cfg = Config::from_string(&cfg_str.replace("bash", "bash\nfunction_prefix: my-"));
+        assert!(cfg.is_err());
+        assert_eq!(cfg.unwrap_err(), "Invalid function prefix 'my-' in line 2");
+    }
 }
@@ -1,11 +1,14 @@
 mod bash;
+mod bundle;
 mod cli;
+mod compiled;
+mod complete;
 mod config;
 mod zsh;
 
-use cli::Cli;
+use cli::{Bundle, Cli, Complete};
 use config::*;
-use std::{error::Error, fs, io, process::exit};
+use std::{error::Error, fs, io, path::Path, process::exit};
 
 fn show_cfg_help_and_exit() -> ! {
     println!(
@@ -15,16 +18,54 @@ has the following format:
 
 shell:        <shell_type> (bash|zsh)
 program_name: <program_name>
+program_names?: [<program_name>, ...]
+complete_paths?: (true|false) [default: false]
+complete_env_names?: (true|false) [default: false]
+function_prefix?: <prefix> [default: _]
 use_equals_sign: (true|false) [default: true] (available only for zsh)
+external_subcommands?: <prefix>
+command_line_after?: [<word>, ...]
+section*: <category>
 option*:
     short?: <short_name> _
                           |-> At least one should exist
     long?:  <long_name>  ‾
     accepts_value?:    (true|false) [default: true]  (available only for zsh)
     accepts_files?:    (true|false) [default: false]
+    files?:            [all|dirs|executables|<glob>] [no_hidden]
+    value_type?:       <value_type>
+    terminator?:       <terminator>
     accepts_multiple?: (true|false) [default: false] (available only for zsh)
     description?:
     fixed_values?:     [<fixed_value>, ...]
+    fixed_values_file?:         <file>
+    fixed_values_from_command?: <command>
+    arity?:            <count> | <min>..<max> [default: 1]
+    arg*:              <name> [files[(<files>)] | [<fixed_value>, ...] | <value_type>]
+    value_separator?:  <separator>
+    suboption*:        <key> [files[(<files>)] | [<fixed_value>, ...] | <value_type>]
+    negatable?:        (true|false) [default: false]
+    aliases?:          [<alias>, ...]
+    hide_aliases?:     (true|false) [default: false]
+    attached_value?:   (true|false|only) [default: false]
+    hidden?:           (true|false) [default: false]
+    deprecated?:       (true|false|<message>) [default: false]
+    category?:         <category>
+    value_name?:       <value_name>
+    default?:          <default_value>
+    hint?:             <hint>
+    env?:              <variable>
+    values_command?:   <command>
+    cache_ttl?:        <count>[s|m|h|d]
+value_type <name>*:
+    fixed_values?:     [<fixed_value>, ...]  _
+    values_command?:   <command>              |-> Exactly one should exist
+    files?:            <files>               ‾
+    cache_ttl?:        <count>[s|m|h|d]
+env <variable>*:
+    description?:
+    value_type?, fixed_values?, fixed_values_file?, fixed_values_from_command?,
+    values_command?, files?
 
 Field/Values explanation:
 
@@ -33,8 +74,39 @@ Value: It's the shell you want to generate the script for. Currently supported:
 Mandatory: yes
 
 Field: program_name
-Value: The name of you program to generate the autocompletions for
-Mandatory: yes
+Value: The name of you program to generate the autocompletions for. It can't start with a dash
+       or contain whitespace or characters special to the shell (e.g ; $ * =).
+Mandatory: yes (unless program_names is given)
+
+Field: program_names
+Value: A bracketed comma separated list of names the program is also run as (e.g [ourtool,
+       ourtool-dev]). The completion is registered for all of them. If program_name is missing
+       the first name is used in its place.
+Mandatory: no
+
+Field: complete_paths
+Value: Denotes whether path invocations (e.g ./target/debug/ourtool) whose last component is one
+       of the program names are completed as well. bash always does this.
+Default: false
+Mandatory: no
+
+Field: complete_env_names
+Value: Denotes whether the environment variables of the env <variable> blocks and env fields
+       are offered when the initial word of any command starts like one of them
+       (e.g OURTOOL_<TAB>). This takes over the completion of the initial word for the whole
+       shell (bash 5.0 or later), handing over to the previous completer otherwise.
+Default: false
+Mandatory: no
+
+Field: function_prefix
+Value: The prefix of the names of the generated shell functions, which are followed by the
+       program name (e.g with _acs_ the functions of ourtool start with _acs_ourtool). Characters
+       of the program name that can't be part of a function name are written as their hex code
+       between underscores (my.tool becomes my_2e_tool) and underscores are doubled, so scripts
+       for any number of programs can be loaded into the same shell. It consists of letters,
+       digits and underscores.
+Default: _
+Mandatory: no
 
 Field: use_equals_sign
 Value: Denotes whether we want to add an equals sign (=) after option completion
@@ -42,16 +114,54 @@ Value: Denotes whether we want to add an equals sign (=) after option completion
 Default: true
 Mandatory: no
 
+Field: external_subcommands
+Value: The prefix of executables in PATH that act as subcommands of the program, like git and
+       cargo do (e.g with ourtool- the executable ourtool-foo is run as `ourtool foo`).
+       They are discovered at completion time and once one is chosen, the rest of the line is
+       completed by the completion registered for that executable, if there is one.
+Mandatory: no
+
+Field: command_line_after
+Value: A bracketed comma separated list of words after which the rest of the line is a command
+       followed by its arguments (e.g [--] for `ourtool exec -- <cmd> <args...>`). The word after
+       the first of them is completed as a command name and the words after that by the
+       command's own completion, like the command_line value type does for options.
+Mandatory: no
+
+Field: value_type <name>
+Value: None. Starts the definition of a named value type that options refer to with
+       value_type: <name> (or in place of a value specification in arg and suboption fields).
+       The values are given by exactly one of fixed_values, values_command or files
+       (fixed_values_file and fixed_values_from_command count as fixed_values).
+       Names consist of letters, digits and underscores and can't be a built-in value type.
+       Value types may be defined before or after the options that use them.
+Mandatory: no
+
+Field: env <variable>
+Value: None. Describes an environment variable that the program reads (e.g OURTOOL_HOME).
+       zsh completes its values after the `=` of an assignment in front of a command. bash
+       can't: it doesn't run completion functions for the value of an assignment in front of
+       a command and completes file names there. With complete_env_names the variables are
+       also offered for a word in front of the command that starts like one of them.
+       The values are given by the same fields as for options, and the variable names the
+       options give with their env field are included too.
+Mandatory: no
+
+Field: section
+Value: The category of the options that follow it, up to the next section field. Options may
+       override it with their own category field.
+Mandatory: no
+
 Field: option
 Value: None. The option field gets no value. It starts a new option description
 Mandatory: no
 
 Field: short
-Value: The short option description (- must be included)
+Value: The short option description, including its prefix (e.g -v, +x)
 Mandatory: no*
 
 Field: long
-Value: The long option description (-- must be included)
+Value: The long option description, including its prefix (e.g --verbose, -verbose)
 Mandatory: no*
 
 Field: accepts_value
@@ -65,6 +175,29 @@ Value: Denotes whether that option takes files/directories as value(s). Must be
 Default: false
 Mandatory: no
 
+Field: files
+Value: Restricts the files that are completed for the option and implies accepts_files.
+       It takes one of all, dirs (directories only), executables or a glob (e.g *.toml),
+       optionally followed by no_hidden to leave out hidden files. The same filter can be given
+       to arg and suboption fields as files(<filter>), e.g files(*.toml no_hidden).
+Default: all
+Mandatory: no
+
+Field: value_type
+Value: The kind of value the option takes, completed with the native completer of each shell.
+       One of path, dir, executable, command, user, group, host, port (or service), pid,
+       signal, env_var, interface, locale or timezone. Value types may also be used in place
+       of a value specification in arg and suboption fields. The name of a value type
+       defined with a value_type <name> block may be used as well.
+       The special command_line type (e.g --wrap <cmd> <args...>) completes a command name and
+       then hands the rest of the line over to that command's own completion.
+Mandatory: no
+
+Field: terminator
+Value: The word that ends a command_line value (e.g ; for find -exec ... ;). Words after the
+       terminator are completed as arguments of the program again.
+Mandatory: no
+
 Field: accepts_multiple
 Value: This value denotes whether the option can appear multiple times in the cli
        or take multiple values (which is the same thing). This is valid only for zsh (for now).
@@ -83,7 +216,132 @@ Value: This value is a bracketed comma separated list of fixed values that will
        This is valid only for zsh.
 Mandatory: no
 
-* short and long fields are not mandatory, however if you define an option at least one of them must be present."
+Field: fixed_values_file
+Value: A file whose non empty lines are used as the fixed_values of the option. It is read
+       when the configuration is parsed and a relative path is resolved against the directory
+       of the configuration file. It may also be given in value_type <name> blocks.
+Mandatory: no
+
+Field: fixed_values_from_command
+Value: A command whose non empty output lines are used as the fixed_values of the option.
+       It is run with sh when a script is generated or the configuration is compiled, in the
+       directory of the configuration file, and must succeed. --lint, --runtime and
+       autocshell complete don't run it, so a configuration that isn't compiled gives no values
+       for it at completion time. It may also be given in value_type <name> blocks.
+Mandatory: no
+
+Field: values_command
+Value: A command that is run at completion time. Each line of its output is a value.
+       The command may refer to the value already given to another option on the command
+       line with {{<option name>}} (e.g ourtool ns list --cluster {{--cluster}}). The reference is
+       empty if the option hasn't been given.
+Mandatory: no
+
+Field: cache_ttl
+Value: How long the output of the values_command is kept on disk before the command is run
+       again. A number of seconds, optionally followed by s, m, h or d (e.g 90, 5m, 1h).
+       The output is stored under $XDG_CACHE_HOME/autocshell/<program_name>/ (or
+       ~/.cache/autocshell/<program_name>/), keyed by the command and the values of the
+       options it refers to. It may also be given in value_type <name> blocks.
+Mandatory: no
+
+Field: arity
+Value: The number of values the option takes each time it appears (e.g --point X Y).
+       Either a fixed count or a range of the form <min>..<max>.
+Default: 1 (or the number of arg fields, if any are given)
+Mandatory: no
+
+Field: arg
+Value: Describes the value at the next position after the option. It consists of a name
+       followed optionally by `files` or a bracketed list of fixed values. Positions without
+       an arg field use the accepts_files and fixed_values fields of the option.
+Mandatory: no
+
+Field: value_separator
+Value: Denotes that the option takes a list of values separated by the given separator
+       (e.g --features a,b,c). Each element is completed from the accepts_files and fixed_values
+       fields and elements that are already present are not suggested again.
+       The separator may be surrounded by double quotes.
+Mandatory: no
+
+Field: suboption
+Value: Describes a key of a key=value list (e.g -o ro,uid=1000). It consists of the key
+       followed optionally by `files` or a bracketed list of fixed values. Keys without values
+       are completed as plain flags. If no value_separator is given a comma is used.
+Mandatory: no
+
+Field: negatable
+Value: Denotes whether the option also has a --no-<long> form. The negated form gets a derived
+       description and the two forms exclude each other during completion. Requires a long name.
+Default: false
+Mandatory: no
+
+Field: aliases
+Value: A bracketed comma separated list of additional names for the option (e.g [--colour, -?]).
+       Aliases are treated exactly like the short and long names of the option.
+Mandatory: no
+
+Field: hide_aliases
+Value: Denotes whether the aliases should be left out when listing options. Hidden aliases are
+       still recognised, so their values are completed.
+Default: false
+Mandatory: no
+
+Field: attached_value
+Value: Denotes whether the value can be glued to the short name of the option (e.g -ofile, -O2).
+       With true the value may be attached or given as the next word, with only it must be attached.
+       Requires a short name and an option that accepts a value.
+Default: false
+Mandatory: no
+
+Field: hidden
+Value: Denotes whether the option should be left out when listing options. Hidden options are
+       still recognised, so their values are completed.
+Default: false
+Mandatory: no
+
+Field: deprecated
+Value: Marks the option as deprecated, optionally with a message (e.g \"use --new instead\")
+       that is shown next to its description. Deprecated options are reported by --lint.
+Default: false
+Mandatory: no
+
+Field: category
+Value: The category the option is listed under. zsh lists the options of each category as a
+       separate group, headed by the category when the format style of the completion system is
+       set, through the tag-order, group-name and ignored-patterns styles of the program.
+       bash puts a header line before them when options have descriptions and all of them are
+       listed on a second TAB. The headers are never offered as candidates, so menu completion
+       and show-all-if-ambiguous list the options without them. Options without a category
+       come first.
+Mandatory: no
+
+Field: value_name
+Value: A placeholder for the value of the option (e.g PORT), shown by zsh when completing the value.
+Mandatory: no
+
+Field: default
+Value: The value the option has when it isn't given, shown next to the value's hint.
+Mandatory: no
+
+Field: hint
+Value: A short description of the value of the option (e.g \"TCP port (1-65535)\").
+       For options whose values can't be completed, zsh shows the value_name, hint and default
+       as a message and bash lists them on a second TAB instead of falling back to file
+       names.
+Mandatory: no
+
+Field: env
+Value: An environment variable that can be set instead of giving the option. It is shown in the
+       description of the option and completed like the env <variable> blocks, using the
+       description and values of the option.
+Mandatory: no
+
+* short and long fields are not mandatory, however if you define an option at least one of them must be present.
+
+Option names (short, long and aliases) may use any prefix made of '-' and '+' characters, so
+single dash long options (-verbose) and set style options (+x) are supported. An option that
+has both -x and +x as names is completed with zsh's -+x form."
     );
 
     exit(0);
@@ -100,14 +358,41 @@ fn write_script_to_file(script: &str, filename: &str, cfg: &Config) -> io::Resul
     Ok(())
 }
 
-fn generate_script(cfg: &Config) -> Option<String> {
-    if cfg.shell == "bash" {
-        Some(bash::generate_bash(cfg))
-    } else if cfg.shell == "zsh" {
-        Some(zsh::generate_zsh(cfg))
-    } else {
-        None
+fn generate_script(cfg: &Config, runtime_spec: Option<&str>) -> Option<String> {
+    match (cfg.shell.as_str(), runtime_spec) {
+        ("bash", None) => Some(bash::generate_bash(cfg)),
+        ("bash", Some(spec_file)) => Some(bash::generate_bash_runtime(cfg, spec_file)),
+        ("zsh", None) => Some(zsh::generate_zsh(cfg)),
+        ("zsh", Some(spec_file)) => Some(zsh::generate_zsh_runtime(cfg, spec_file)),
+        _ => None,
+    }
+}
+
+fn print_completions(complete: &Complete) -> Result<(), Box<dyn Error>> {
+    let cfg = compiled::load_spec(&complete.spec_file, FixedValuesCommands::Skip)?;
+    let words = complete.words.iter().map(|w| w.as_str()).collect::<Vec<_>>();
+    print!("{}", complete::format_completions(&cfg, &words));
+    Ok(())
+}
+
+fn write_bundle(bundle: &Bundle) -> Result<(), Box<dyn Error>> {
+    let cfgs = bundle
+        .cfg_files
+        .iter()
+        .map(|cfg_file| Config::from_file(cfg_file, FixedValuesCommands::Run))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for shell in &bundle.shells {
+        for file in bundle::generate_bundle(&cfgs, shell)? {
+            let path = Path::new(&bundle.output_dir).join(&file.path);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, file.contents)?;
+        }
     }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -117,7 +402,35 @@ fn main() -> Result<(), Box<dyn Error>> {
         show_cfg_help_and_exit();
     }
 
-    let mut cfg = Config::from_file(&cli.cfg_file)?;
+    if let Some(bundle) = &cli.bundle {
+        return write_bundle(bundle);
+    }
+
+    if let Some(complete) = &cli.complete {
+        return print_completions(complete);
+    }
+
+    if let Some(compile) = &cli.compile {
+        compiled::compile(&compile.cfg_file, &compile.output_file)?;
+        return Ok(());
+    }
+
+    // Only the static scripts hold the values of fixed_values_from_command fields.
+    let commands = if cli.lint || cli.runtime {
+        FixedValuesCommands::Skip
+    } else {
+        FixedValuesCommands::Run
+    };
+    let mut cfg = compiled::load_spec(&cli.cfg_file, commands)?;
+    if cli.lint {
+        let warnings = cfg.lint();
+        for warning in &warnings {
+            eprintln!("warning: {}", warning);
+        }
+
+        exit(if warnings.is_empty() { 0 } else { 1 });
+    }
+
     if cfg.shell.is_empty() {
         if let Some(shell) = cli.shell {
             cfg.shell = shell;
@@ -127,7 +440,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let script = generate_script(&cfg);
+    // The script refers to the configuration wherever it is run from.
+    let runtime_spec = if cli.runtime {
+        Some(fs::canonicalize(&cli.cfg_file)?.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    let script = generate_script(&cfg, runtime_spec.as_deref());
 
     match script {
         Some(script) => match cli.output_file {
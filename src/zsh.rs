@@ -1,113 +1,474 @@
 use crate::config::*;
 use std::cell::RefCell;
 
+fn files_action(filter: &FileFilter) -> String {
+    let files = match &filter.kind {
+        FileKind::All => "_files".to_string(),
+        FileKind::Glob(glob) => std::format!("_files -g \"{}\"", glob),
+        FileKind::Directories => "_files -/".to_string(),
+        FileKind::Executables => "_files -g \"*(-*)\"".to_string(),
+    };
+
+    if filter.include_hidden {
+        files
+    } else {
+        std::format!("{} -F \"(.*)\"", files)
+    }
+}
+
+fn value_kind_message_and_action(kind: ValueKind) -> (&'static str, &'static str) {
+    match kind {
+        ValueKind::Command => ("command", "_command_names -e"),
+        ValueKind::User => ("user", "_users"),
+        ValueKind::Group => ("group", "_groups"),
+        ValueKind::Host => ("host", "_hosts"),
+        ValueKind::Port => ("port", "_ports"),
+        ValueKind::Pid => ("process ID", "_pids"),
+        ValueKind::Signal => ("signal", "_signals"),
+        ValueKind::EnvVar => ("environment variable", "_parameters -g \"*export*\""),
+        ValueKind::Interface => ("interface", "_net_interfaces"),
+        ValueKind::Locale => ("locale", "_locales"),
+        ValueKind::Timezone => ("timezone", "_time_zone"),
+        ValueKind::CommandLine => ("command line", "_normal"),
+    }
+}
+
 #[inline]
-fn file_options(option: &ProgramOption) -> String {
-    if option.accepts_files {
-        ":file:_files".to_string()
-    } else if option.accepts_value {
-        if !option.fixed_values.is_empty() {
-            std::format!(": :({})", option.fixed_values.join(" "))
-        } else {
-            ": :".to_string()
+fn value_type_function_name(cfg: &Config, name: &str) -> String {
+    std::format!("{}_{}_values", cfg.function_name(), name)
+}
+
+/// The value given on the line to the option with the given name, for use in values commands.
+fn option_value(cfg: &Config, name: &str) -> String {
+    let names = cfg
+        .option_named(name)
+        .map(|option| option.names())
+        .unwrap_or_else(|| vec![name]);
+
+    std::format!(
+        "\"$({}_option_value {})\"",
+        cfg.function_name(),
+        names
+            .iter()
+            .map(|name| option_name(name))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Runs the command, through the disk cache if it has a cache_ttl. The command is keyed
+/// by its text and the values of the options it refers to.
+fn values_command_output(cfg: &Config, command: &ValuesCommand) -> String {
+    let expanded = expand_option_references(&command.command, |name| option_value(cfg, name));
+    match command.cache_ttl {
+        None => std::format!("{} 2>/dev/null", expanded),
+        Some(ttl) => std::format!(
+            "{function_name}_cached {ttl} '{command}'{references}",
+            function_name = cfg.function_name(),
+            ttl = ttl,
+            command = expanded.replace('\'', "'\\''"),
+            references = option_references(&command.command)
+                .iter()
+                .map(|(_, name)| std::format!(" {}", option_value(cfg, name)))
+                .collect::<String>()
+        ),
+    }
+}
+
+/// Output lines of `command`, quoted for use inside a single-quoted `_arguments` spec.
+#[inline]
+fn command_output_action(cfg: &Config, command: &ValuesCommand) -> String {
+    std::format!(
+        "{{compadd -- ${{(f)\"$({})\"}}}}",
+        values_command_output(cfg, command).replace('\'', "'\\''")
+    )
+}
+
+fn value_spec_message_and_action(cfg: &Config, spec: &ValueSpec) -> (String, String) {
+    match spec {
+        ValueSpec::Kind(kind) => {
+            let (message, action) = value_kind_message_and_action(*kind);
+            (message.to_string(), action.to_string())
         }
+        ValueSpec::Any => (" ".to_string(), String::new()),
+        ValueSpec::Files(filter) => ("file".to_string(), files_action(filter)),
+        ValueSpec::Fixed(values) => (" ".to_string(), std::format!("({})", values.join(" "))),
+        ValueSpec::Command(command) => (" ".to_string(), command_output_action(cfg, command)),
+        ValueSpec::Named(name) => (name.replace('_', " "), value_type_function_name(cfg, name)),
+    }
+}
+
+fn multiple_values_options(cfg: &Config, option: &ProgramOption, arity: Arity) -> String {
+    (0..arity.max)
+        .map(|position| {
+            let arg = option.arg_at(position);
+            let (default_message, action) = value_spec_message_and_action(cfg, &arg.spec);
+            let message = if arg.name.is_empty() {
+                default_message
+            } else {
+                arg.name.clone()
+            };
+            let colons = if position < arity.min { ":" } else { "::" };
+
+            std::format!("{}{}:{}", colons, message, action)
+        })
+        .collect()
+}
+
+fn value_list_action(cfg: &Config, option: &ProgramOption) -> String {
+    let separator = option.value_separator.replace('"', "\\\"");
+    let message = if option.long.is_empty() {
+        option.short.trim_start_matches('-')
     } else {
-        String::new()
+        option.long.trim_start_matches('-')
+    };
+
+    if !option.suboptions.is_empty() {
+        let suboptions = option
+            .suboptions
+            .iter()
+            .map(|suboption| match suboption.spec {
+                ValueSpec::Any => std::format!("\"{}\"", suboption.name),
+                _ => {
+                    let (_, action) = value_spec_message_and_action(cfg, &suboption.spec);
+                    std::format!(
+                        "\"{name}:{name}:{action}\"",
+                        name = suboption.name,
+                        action = action
+                    )
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        return std::format!(
+            "_values -s \"{}\" \"{}\" {}",
+            separator, message, suboptions
+        );
+    }
+
+    match option.value_spec() {
+        ValueSpec::Fixed(values) => std::format!(
+            "_values -s \"{}\" \"{}\" {}",
+            separator,
+            message,
+            values.join(" ")
+        ),
+        ValueSpec::Any => String::new(),
+        spec => {
+            let (_, action) = value_spec_message_and_action(cfg, &spec);
+            std::format!("_sequence -s \"{}\" {}", separator, action)
+        }
     }
 }
 
-fn get_option_attributes(cfg: &Config, option: &ProgramOption) -> String {
-    let maybe_equals_sign =
-        if !option.is_help() && !option.has_one_representation() && cfg.use_equals_sign {
-            "="
+/// Hands the rest of the line (up to the terminator, if any) over to the command's own completion.
+fn command_line_options(option: &ProgramOption) -> String {
+    let terminator = option
+        .terminator
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_string()
+            } else {
+                std::format!("\\{}", c)
+            }
+        })
+        .collect::<String>();
+
+    std::format!(":*{}::command line:_normal", terminator)
+}
+
+#[inline]
+fn file_options(cfg: &Config, option: &ProgramOption) -> String {
+    if option.delegates_command_line() {
+        command_line_options(option)
+    } else if let Some(arity) = option.arity {
+        multiple_values_options(cfg, option, arity)
+    } else if option.takes_value_list() {
+        std::format!(": :{}", value_list_action(cfg, option))
+    } else if option.accepts_files || option.accepts_value {
+        let (message, action) = value_spec_message_and_action(cfg, &option.value_spec());
+        let message = if option.describes_value() {
+            option
+                .value_description()
+                .replace(':', "\\:")
+                .replace('\'', "'\\''")
         } else {
-            ""
+            message
         };
+        std::format!(":{}:{}", message, action)
+    } else {
+        String::new()
+    }
+}
+
+fn get_option_attributes(cfg: &Config, option: &ProgramOption, name: &str) -> String {
+    let attachment = if name == option.short {
+        match option.attached_value {
+            AttachedValue::No => None,
+            AttachedValue::Allowed => Some("+"),
+            AttachedValue::Required => Some("-"),
+        }
+    } else {
+        None
+    };
+
+    let maybe_equals_sign = if let Some(attachment) = attachment {
+        attachment
+    } else if !option.is_help() && !option.has_one_representation() && cfg.use_equals_sign {
+        "="
+    } else {
+        ""
+    };
 
     format!(
         "{equals_sign}[{desc}]{file_options}",
         equals_sign = maybe_equals_sign,
-        desc = option.description,
-        file_options = file_options(option)
+        desc = option.listed_description(),
+        file_options = file_options(cfg, option)
     )
 }
 
-fn format_option_with_multiple_args(cfg: &Config, option: &ProgramOption) -> String {
-    let option_attributes = get_option_attributes(cfg, option);
-    let maybe_backslash = if option.has_one_representation() {
-        ""
+/// Escapes option names that would otherwise be expanded as glob patterns.
+fn option_name(name: &str) -> String {
+    if name.contains(|c| "*?[]#~^".contains(c)) {
+        format!("'{}'", name)
     } else {
-        "\\"
-    };
+        name.to_owned()
+    }
+}
 
-    let short = if !option.short.is_empty() {
-        format!(
-            "\t\t'*{opt}{attributes}'{backslash}",
-            opt = option.short,
-            attributes = option_attributes,
-            backslash = maybe_backslash
-        )
-    } else {
-        String::new()
-    };
+/// Returns the names to list for the option, merging `-x` and `+x` into zsh's `-+x` form.
+fn listed_names(option: &ProgramOption) -> Vec<String> {
+    let visible_names = option.visible_names();
+    visible_names
+        .iter()
+        .filter_map(|name| {
+            if let Some(rest) = name.strip_prefix('+') {
+                if visible_names.contains(&format!("-{}", rest).as_str()) {
+                    return None;
+                }
+            } else if let Some(rest) = name.strip_prefix('-') {
+                if !rest.starts_with('-') && visible_names.contains(&format!("+{}", rest).as_str()) {
+                    return Some(format!("-+{}", rest));
+                }
+            }
 
-    let long = if !option.long.is_empty() {
-        format!(
-            "\t\t'*{opt}{attributes}{backslash}'",
-            opt = option.long,
-            attributes = option_attributes,
-            backslash = maybe_backslash
-        )
-    } else {
-        String::new()
+            Some(name.to_string())
+        })
+        .collect()
+}
+
+fn format_hidden_aliases(cfg: &Config, option: &ProgramOption, prefix: &str) -> String {
+    option
+        .hidden_aliases()
+        .iter()
+        .map(|alias| {
+            format!(
+                " \\\n\t\t'!{prefix}{opt}{attributes}'",
+                prefix = prefix,
+                opt = alias,
+                attributes = get_option_attributes(cfg, option, alias)
+            )
+        })
+        .collect()
+}
+
+/// Escapes the characters of an option or program name that are special in zsh patterns.
+fn pattern_escape(name: &str) -> String {
+    name.chars()
+        .flat_map(|c| {
+            let escape = "\\*?[]()|<>^#~".contains(c);
+            std::iter::once('\\').filter(move |_| escape).chain(std::iter::once(c))
+        })
+        .collect()
+}
+
+/// Lists the options of each category as a separate group, headed by the category's name.
+/// The options stay in the _arguments specs, so they keep their exclusion lists, and the
+/// tag-order style splits the options tag into a label per category, each of which ignores
+/// the options of the others and is a group of its own. Options without a category come first.
+fn format_category_styles(cfg: &Config) -> String {
+    let categories = cfg.categories();
+    if categories.is_empty() {
+        return String::new();
+    }
+
+    let listed_names = |category: &str| {
+        cfg.program_options
+            .iter()
+            .filter(|o| !o.hidden && o.category == category)
+            .flat_map(|o| {
+                let negated = o.negated().map(|negated| pattern_escape(&negated.long));
+                o.visible_names()
+                    .into_iter()
+                    .map(pattern_escape)
+                    .chain(negated)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
     };
 
-    let maybe_newline = if !short.is_empty() && !long.is_empty() {
-        "\n"
-    } else {
-        ""
+    let mut labels = vec![("other".to_owned(), "option", listed_names(""))];
+    labels.extend(categories.iter().enumerate().map(|(i, category)| {
+        (format!("category-{}", i + 1), *category, listed_names(category))
+    }));
+    labels.retain(|(_, _, names)| !names.is_empty());
+
+    let context = match cfg.command_names().as_slice() {
+        [name] => pattern_escape(name),
+        names => format!(
+            "({})",
+            names.iter().map(|name| pattern_escape(name)).collect::<Vec<_>>().join("|")
+        ),
     };
 
-    format!("{}{newline}{}", short, long, newline = maybe_newline)
+    let tag_order = labels
+        .iter()
+        .map(|(label, description, _)| {
+            format!(
+                "options:-{}:{}",
+                label,
+                description.replace('\\', "\\\\").replace(' ', "\\ ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let ignored_patterns = labels
+        .iter()
+        .filter(|_| labels.len() > 1)
+        .map(|(label, _, _)| {
+            let others = labels
+                .iter()
+                .filter(|(other, _, _)| other != label)
+                .flat_map(|(_, _, names)| names.iter().map(|name| name.as_str()))
+                .collect::<Vec<_>>();
+            format!(
+                "\nzstyle ':completion:*:*:{context}:*:options-{label}' ignored-patterns '({names})(|=)'",
+                context = context,
+                label = label,
+                names = others.join("|").replace('\'', "'\\''")
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "\n\nzstyle ':completion:*:*:{context}:*' tag-order '{tag_order}'\n\
+        zstyle ':completion:*:*:{context}:*:options-*' group-name ''{ignored_patterns}",
+        context = context,
+        tag_order = tag_order.replace('\'', "'\\''"),
+        ignored_patterns = ignored_patterns
+    )
+}
+
+/// Hidden options are still recognised, so their values are completed, but are never listed.
+fn format_hidden_option(cfg: &Config, option: &ProgramOption) -> String {
+    let prefix = if option.accepts_multiple { "*" } else { "" };
+    let negated = option
+        .negated()
+        .map(|negated| format!("\t\t'!{}[{}]'", negated.long, negated.description));
+
+    option
+        .names()
+        .iter()
+        .map(|name| {
+            format!(
+                "\t\t'!{prefix}{opt}{attributes}'",
+                prefix = prefix,
+                opt = name,
+                attributes = get_option_attributes(cfg, option, name)
+            )
+        })
+        .chain(negated)
+        .collect::<Vec<_>>()
+        .join(" \\\n")
+}
+
+fn format_option_with_multiple_args(cfg: &Config, option: &ProgramOption) -> String {
+    let visible = listed_names(option)
+        .iter()
+        .map(|name| {
+            format!(
+                "\t\t'*{opt}{attributes}'",
+                opt = name,
+                attributes = get_option_attributes(cfg, option, name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" \\\n");
+
+    format!("{}{}", visible, format_hidden_aliases(cfg, option, "*"))
 }
 
 fn format_option_group(cfg: &Config, option: &ProgramOption, group_num: u32) -> String {
-    let option_attributes = get_option_attributes(cfg, option);
-    let maybe_comma = if option.has_one_representation() {
-        ""
+    let visible_names = listed_names(option);
+    let representations = if option.attached_value != AttachedValue::No {
+        visible_names
+            .iter()
+            .map(|name| {
+                format!(
+                    "'{}{}'",
+                    name,
+                    get_option_attributes(cfg, option, name)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" \\\n\t\t")
+    } else if visible_names.len() == 1 {
+        format!(
+            "{}'{}'",
+            option_name(&visible_names[0]),
+            get_option_attributes(cfg, option, &visible_names[0])
+        )
     } else {
-        ","
+        format!(
+            "{{{names}}}'{attributes}'",
+            names = visible_names
+                .iter()
+                .map(|name| option_name(name))
+                .collect::<Vec<_>>()
+                .join(","),
+            attributes = get_option_attributes(cfg, option, "")
+        )
+    };
+
+    let hidden_aliases = format_hidden_aliases(cfg, option, "");
+
+    let negated = match option.negated() {
+        Some(negated) => format!(
+            " \\\n\t\t'{opt}[{desc}]'",
+            opt = negated.long,
+            desc = negated.description
+        ),
+        None => String::new(),
     };
 
     format!(
         "\t\t+ '(group_{num})' \\\n\
-        \t\t{{{short}{comma}{long}}}'{attributes}'",
+        \t\t{representations}{hidden_aliases}{negated}",
         num = group_num,
-        short = option.short,
-        comma = maybe_comma,
-        long = option.long,
-        attributes = option_attributes
+        representations = representations,
+        hidden_aliases = hidden_aliases,
+        negated = negated
     )
 }
 
 fn format_option_with_one_representation(cfg: &Config, option: &ProgramOption) -> String {
-    let opt = if option.short.is_empty() {
-        &option.long
-    } else {
-        &option.short
-    };
-
-    let option_attributes = get_option_attributes(cfg, option);
+    let opt = option.names()[0];
 
     format!(
         "\t\t'{opt}{attributes}'",
         opt = opt,
-        attributes = option_attributes
+        attributes = get_option_attributes(cfg, option, opt)
     )
 }
 
 fn get_option_priority(option: &ProgramOption) -> u32 {
-    if option.has_one_representation() {
+    if option.negatable {
+        3
+    } else if option.has_one_representation() {
         1
     } else if option.accepts_multiple {
         2
@@ -128,7 +489,12 @@ pub fn generate_zsh(cfg: &Config) -> String {
     let arguments = program_options
         .iter()
         .map(|option| {
-            if option.accepts_multiple {
+            if option.hidden {
+                format_hidden_option(cfg, option)
+            } else if option.negatable {
+                *group_counter.borrow_mut() += 1;
+                format_option_group(cfg, option, *group_counter.borrow())
+            } else if option.accepts_multiple {
                 format_option_with_multiple_args(cfg, option)
             } else if option.has_one_representation() {
                 format_option_with_one_representation(cfg, option)
@@ -140,14 +506,428 @@ pub fn generate_zsh(cfg: &Config) -> String {
         .collect::<Vec<_>>()
         .join(" \\\n");
 
+    let value_type_functions = format!(
+        "{}{}{}{}{}",
+        format_value_type_functions(cfg),
+        format_option_value_function(cfg),
+        format_cache_function(cfg),
+        format_env_functions(cfg),
+        format_category_styles(cfg)
+    );
+
+    if !cfg.external_subcommands.is_empty() {
+        return format_with_external_subcommands(cfg, &arguments, &value_type_functions);
+    }
+
     format!(
         "\
-    compdef _{prog_name} {prog_name}\n\n\
-    function _{prog_name}() {{\n\
+    {compdef}\n\n\
+    function {function_name}() {{\n\
+        {command_line_after}\
         \t_arguments \\\n\
         {arguments}\n\
-    }}",
+    }}{value_type_functions}",
+        compdef = format_compdef(cfg),
+        function_name = cfg.function_name(),
+        command_line_after = format_command_line_after(cfg),
+        arguments = arguments,
+        value_type_functions = value_type_functions
+    )
+}
+
+fn format_cache_function(cfg: &Config) -> String {
+    if !cfg.uses_cache() {
+        return String::new();
+    }
+
+    format!(
+        "\n\nfunction {function_name}_cached() {{\n\
+        \tlocal ttl=$1 command=$2\n\
+        \tlocal dir=${{XDG_CACHE_HOME:-$HOME/.cache}}/autocshell/{prog_name}\n\
+        \tlocal key=$(print -rl -- \"${{@[2,-1]}}\" | cksum)\n\
+        \tlocal file=$dir/${{key%% *}} output\n\
+        \tlocal -a fresh\n\
+        \tfresh=( $file(Nms-$ttl) )\n\
+        \tif (( $#fresh )); then\n\
+        \t\tcat $file\n\
+        \t\treturn\n\
+        \tfi\n\n\
+        \toutput=$(eval \"$command\" 2>/dev/null)\n\
+        \tif [[ -n $output ]] && mkdir -p $dir; then\n\
+        \t\tprint -r -- $output > $file.$$ && mv -f $file.$$ $file\n\
+        \tfi\n\
+        \tprint -r -- $output\n\
+        }}",
+        function_name = cfg.function_name(),
+        prog_name = cfg.program_name
+    )
+}
+
+/// Prints the value of the last occurrence of any of the given option names before the
+/// current word, whether it was given as `--opt value` or `--opt=value`.
+fn format_option_value_function(cfg: &Config) -> String {
+    if !cfg.uses_option_references() {
+        return String::new();
+    }
+
+    format!(
+        "\n\nfunction {function_name}_option_value() {{\n\
+        \tlocal i name value\n\
+        \tfor (( i = 2; i < CURRENT; i++ )); do\n\
+        \t\tfor name in \"$@\"; do\n\
+        \t\t\tif [[ $words[i] == \"$name\" ]] && (( i + 1 < CURRENT )); then\n\
+        \t\t\t\tvalue=$words[i+1]\n\
+        \t\t\telif [[ $words[i] == \"$name\"=* ]]; then\n\
+        \t\t\t\tvalue=${{words[i]#*=}}\n\
+        \t\t\tfi\n\
+        \t\tdone\n\
+        \tdone\n\
+        \tprint -r -- $value\n\
+        }}",
+        function_name = cfg.function_name()
+    )
+}
+
+/// Emits one function per named value type, passing compadd's options through to the completer.
+fn format_value_type_functions(cfg: &Config) -> String {
+    cfg.value_types
+        .iter()
+        .map(|value_type| {
+            format!(
+                "\n\nfunction {function}() {{\n\
+                \t{body}\n\
+                }}",
+                function = value_type_function_name(cfg, &value_type.name),
+                body = value_function_body(cfg, &value_type.spec, "value")
+            )
+        })
+        .collect()
+}
+
+/// The body of a function that completes the values of `spec`, passing its arguments to compadd.
+fn value_function_body(cfg: &Config, spec: &ValueSpec, message: &str) -> String {
+    match spec {
+        ValueSpec::Any => std::format!("_message '{}'", message.replace('\'', "'\\''")),
+        ValueSpec::Fixed(values) => std::format!("compadd \"$@\" -- {}", values.join(" ")),
+        ValueSpec::Command(command) => std::format!(
+            "compadd \"$@\" -- ${{(f)\"$({})\"}}",
+            values_command_output(cfg, command)
+        ),
+        spec => {
+            let (_, action) = value_spec_message_and_action(cfg, spec);
+            std::format!("{} \"$@\"", action)
+        }
+    }
+}
+
+/// Registers the completion function for every name of the program and, with complete_paths,
+/// for path invocations (e.g ./target/debug/ourtool) ending in one of them.
+pub fn format_compdef(cfg: &Config) -> String {
+    let names = cfg.command_names();
+    let mut compdef = format!("compdef {} {}", cfg.function_name(), names.join(" "));
+    if cfg.complete_paths {
+        compdef.push_str(&format!(
+            "\ncompdef -p {} {}",
+            cfg.function_name(),
+            names
+                .iter()
+                .map(|name| format!("'*/{}'", name))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+    }
+
+    compdef
+}
+
+/// Completes the value of each environment variable after its `=` and, with
+/// complete_env_names, offers the variables when a word in command position starts like one
+/// (e.g OURTOOL_<TAB>), before handing over to the previous command position completer.
+fn format_env_functions(cfg: &Config) -> String {
+    let env_vars = cfg.all_env_vars();
+    if env_vars.is_empty() {
+        return String::new();
+    }
+
+    let value_functions = env_vars
+        .iter()
+        .map(|env_var| {
+            let message = if env_var.description.is_empty() {
+                &env_var.name
+            } else {
+                &env_var.description
+            };
+
+            format!(
+                "\n\nfunction {function_name}_env_{name}() {{\n\
+                \t{body}\n\
+                }}\n\
+                compdef {function_name}_env_{name} -value-,{name},-default-",
+                function_name = cfg.function_name(),
+                name = env_var.name,
+                body = value_function_body(cfg, &env_var.spec, message)
+            )
+        })
+        .collect::<String>();
+
+    if !cfg.complete_env_names {
+        return value_functions;
+    }
+
+    let names = env_vars
+        .iter()
+        .map(|env_var| {
+            if env_var.description.is_empty() {
+                std::format!("\t\t\t'{}'", env_var.name)
+            } else {
+                std::format!(
+                    "\t\t\t'{}:{}'",
+                    env_var.name,
+                    env_var.description.replace('\'', "'\\''")
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" \\\n");
+
+    format!(
+        "\n\nfunction {function_name}_env_names() {{\n\
+        \tlocal ret=1\n\
+        \tif [[ -n $PREFIX && $PREFIX != *[^A-Za-z0-9_]* ]]; then\n\
+        \t\tlocal -a env_vars\n\
+        \t\tenv_vars=( \\\n\
+        {names}\n\
+        \t\t)\n\
+        \t\t_describe -t environment-variables '{prog_name} environment variable' env_vars -qS= && ret=0\n\
+        \tfi\n\n\
+        \t${{{function_name}_env_names_next:-_autocd}} \"$@\" && ret=0\n\
+        \treturn ret\n\
+        }}\n\
+        if [[ $_comps[-command-] != {function_name}_env_names ]]; then\n\
+        \ttypeset -g {function_name}_env_names_next=$_comps[-command-]\n\
+        \tcompdef {function_name}_env_names -command-\n\
+        fi{value_functions}",
+        function_name = cfg.function_name(),
         prog_name = cfg.program_name,
-        arguments = arguments
+        names = names,
+        value_functions = value_functions
     )
 }
+
+/// Once one of the command_line_after words is on the line, completes the words after it as
+/// a command line of their own.
+fn format_command_line_after(cfg: &Config) -> String {
+    if cfg.command_line_after.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "\tlocal i\n\
+        \tfor (( i = 2; i < CURRENT; i++ )); do\n\
+        \t\tif [[ ${{words[i]}} == ({words}) ]]; then\n\
+        \t\t\twords=(\"${{(@)words[i+1,-1]}}\")\n\
+        \t\t\t(( CURRENT -= i ))\n\
+        \t\t\t_normal\n\
+        \t\t\treturn\n\
+        \t\tfi\n\
+        \tdone\n",
+        words = cfg
+            .command_line_after
+            .iter()
+            .map(|word| format!("'{}'", word))
+            .collect::<Vec<_>>()
+            .join("|")
+    )
+}
+
+/// Offers the `<prefix>*` commands found in PATH as subcommands and hands the
+/// rest of the line over to the completion registered for the chosen command.
+fn format_with_external_subcommands(
+    cfg: &Config,
+    arguments: &str,
+    value_type_functions: &str,
+) -> String {
+    format!(
+        "\
+    {compdef}\n\n\
+    function {function_name}() {{\n\
+        {command_line_after}\
+        \tlocal curcontext=\"$curcontext\" state line\n\
+        \t_arguments -C \\\n\
+        \t\t'1: :->external_subcommand' \\\n\
+        \t\t'*:: :->external_subcommand_args' \\\n\
+        {arguments}\n\n\
+        \tcase $state in\n\
+        \texternal_subcommand)\n\
+        \t\tlocal -a subcommands\n\
+        \t\tsubcommands=(${{${{(k)commands[(I){prefix}*]}}#{prefix}}})\n\
+        \t\t_describe -t external-subcommands 'external subcommand' subcommands\n\
+        \t\t;;\n\
+        \texternal_subcommand_args)\n\
+        \t\twords[1]=\"{prefix}${{words[1]}}\"\n\
+        \t\t_normal\n\
+        \t\t;;\n\
+        \tesac\n\
+    }}{value_type_functions}",
+        compdef = format_compdef(cfg),
+        function_name = cfg.function_name(),
+        command_line_after = format_command_line_after(cfg),
+        prefix = cfg.external_subcommands,
+        arguments = arguments,
+        value_type_functions = value_type_functions
+    )
+}
+
+/// Generates a script that leaves the completion to `autocshell complete`.
+pub fn generate_zsh_runtime(cfg: &Config, spec_file: &str) -> String {
+    format!(
+        "\
+    {compdef}\n\n\
+    function {function_name}() {{\n\
+        \tlocal -a candidates spaced unspaced\n\
+        \tlocal candidate entry ret=1\n\
+        \tcandidates=( ${{(f)\"$(autocshell complete --spec '{spec_file}' -- \"${{(@)words[1,CURRENT]}}\" 2>/dev/null)\"}} )\n\
+        \tfor candidate in $candidates; do\n\
+            \t\tentry=\"${{${{candidate%%$'\\t'*}}//:/\\\\:}}:${{candidate#*$'\\t'}}\"\n\
+            \t\tif [[ ${{candidate%%$'\\t'*}} == *[/=] ]]; then\n\
+                \t\t\tunspaced+=( \"$entry\" )\n\
+            \t\telse\n\
+                \t\t\tspaced+=( \"$entry\" )\n\
+            \t\tfi\n\
+        \tdone\n\
+        \t_describe -t values 'value' spaced && ret=0\n\
+        \t_describe -t values 'value' unspaced -S '' && ret=0\n\
+        \treturn ret\n\
+    }}",
+        compdef = format_compdef(cfg),
+        function_name = cfg.function_name(),
+        spec_file = spec_file.replace('\'', "'\\''")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(config: &str) -> String {
+        generate_zsh(&Config::from_string(config).unwrap())
+    }
+
+    #[test]
+    fn categorized_options_keep_their_exclusions() {
+        let script = script(
+            "\
+            program_names: [prog, prog.dev]
+            option:
+                short: -h
+                long: --help
+                description: Show help
+            section: Build tools
+            option:
+                long: --build
+                aliases: [-b]
+                description: Build it
+            option:
+                long: --cache
+                negatable: true
+                description: Use the cache
+            section: Bob's \\output
+            option:
+                long: --color
+                fixed_values: [auto, never]
+                description: Colour
+            option:
+                long: --secret
+                hidden: true
+            ",
+        );
+
+        assert_eq!(
+            script,
+            r#"compdef _prog prog prog.dev
+
+function _prog() {
+	_arguments \
+		'--color[Colour]: :(auto never)' \
+		'!--secret[]: :' \
+		+ '(group_1)' \
+		{-h,--help}'[Show help]: :' \
+		+ '(group_2)' \
+		{--build,-b}'=[Build it]: :' \
+		+ '(group_3)' \
+		--cache'[Use the cache]: :' \
+		'--no-cache[Negate --cache]'
+}
+
+zstyle ':completion:*:*:(prog|prog.dev):*' tag-order 'options:-other:option options:-category-1:Build\ tools options:-category-2:Bob'\''s\ \\output'
+zstyle ':completion:*:*:(prog|prog.dev):*:options-*' group-name ''
+zstyle ':completion:*:*:(prog|prog.dev):*:options-other' ignored-patterns '(--build|-b|--cache|--no-cache|--color)(|=)'
+zstyle ':completion:*:*:(prog|prog.dev):*:options-category-1' ignored-patterns '(-h|--help|--color)(|=)'
+zstyle ':completion:*:*:(prog|prog.dev):*:options-category-2' ignored-patterns '(-h|--help|--build|-b|--cache|--no-cache)(|=)'"#
+        );
+    }
+
+    #[test]
+    fn names_are_escaped_in_ignored_patterns() {
+        let config = "\
+            program_name: prog
+            option:
+                long: --color
+            section: Globs
+            option:
+                long: --x*
+            option:
+                short: -?
+            ";
+
+        assert!(script(config).ends_with(
+            "\n\nzstyle ':completion:*:*:prog:*' tag-order \
+             'options:-other:option options:-category-1:Globs'\n\
+             zstyle ':completion:*:*:prog:*:options-*' group-name ''\n\
+             zstyle ':completion:*:*:prog:*:options-other' ignored-patterns '(--x\\*|-\\?)(|=)'\n\
+             zstyle ':completion:*:*:prog:*:options-category-1' ignored-patterns '(--color)(|=)'"
+        ));
+
+        // With all the options in one category there's nothing to tell apart.
+        let script = script(
+            "\
+            program_name: prog
+            section: Output
+            option:
+                long: --color
+            option:
+                long: --verbose
+            ",
+        );
+        assert!(script.ends_with(
+            "\n\nzstyle ':completion:*:*:prog:*' tag-order 'options:-category-1:Output'\n\
+             zstyle ':completion:*:*:prog:*:options-*' group-name ''"
+        ));
+    }
+
+    #[test]
+    fn option_values_are_looked_up_literally() {
+        let script = script(
+            "\
+            program_name: prog
+            option:
+                long: --cluster
+                aliases: [-c]
+                fixed_values: [c1, c2]
+            option:
+                long: --ns
+                values_command: echo \"ns-of-{--cluster}\"
+            ",
+        );
+
+        assert!(script.contains(
+            r#"'--ns[]: :{compadd -- ${(f)"$(echo "ns-of-"$(_prog_option_value --cluster -c)"" 2>/dev/null)"}}'"#
+        ));
+        assert!(script.contains(
+            "\t\t\tif [[ $words[i] == \"$name\" ]] && (( i + 1 < CURRENT )); then\n\
+             \t\t\t\tvalue=$words[i+1]\n\
+             \t\t\telif [[ $words[i] == \"$name\"=* ]]; then\n\
+             \t\t\t\tvalue=${words[i]#*=}\n"
+        ));
+    }
+}